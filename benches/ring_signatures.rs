@@ -0,0 +1,129 @@
+//! Verification time of MLSAG and CLSAG signatures for common ring sizes,
+//! and of MLSAG verification with the hash chain computed using plain scalar
+//! multiplications, as it was before the fixed-base generator table.
+//!
+//! Run with `cargo bench --bench ring_signatures`.
+
+use blst_ringct::{
+    blstrs::{
+        group::{ff::Field, prime::PrimeCurveAffine, Curve, Group},
+        G1Affine, G1Projective, Scalar,
+    },
+    hash_to_curve, ClsagSignature, DecoyInput, MlsagMaterial, MlsagSignature, RevealedCommitment,
+    TrueInput,
+};
+use bulletproofs::PedersenGens;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand_core::OsRng;
+use tiny_keccak::{Hasher, Sha3};
+
+const RING_SIZES: [usize; 3] = [11, 32, 128];
+const MSG: &[u8] = b"bench message";
+
+fn material(ring_size: usize) -> (MlsagMaterial, RevealedCommitment) {
+    let true_input = TrueInput {
+        secret_key: Scalar::random(OsRng),
+        revealed_commitment: RevealedCommitment::from_value(5, OsRng),
+    };
+    let decoy_inputs = (1..ring_size)
+        .map(|_| DecoyInput {
+            public_key: G1Projective::random(OsRng).to_affine(),
+            commitment: G1Projective::random(OsRng).to_affine(),
+        })
+        .collect();
+
+    let revealed_pseudo_commitment = true_input.random_pseudo_commitment(OsRng);
+    (
+        MlsagMaterial::new(true_input, decoy_inputs, OsRng),
+        revealed_pseudo_commitment,
+    )
+}
+
+fn hash_to_scalar(material: &[&[u8]]) -> Scalar {
+    let mut sha3 = Sha3::v256();
+    for chunk in material {
+        sha3.update(chunk);
+    }
+    let mut hash = [0u8; 32];
+    sha3.finalize(&mut hash);
+    loop {
+        let s_opt = Scalar::from_bytes_le(&hash);
+        if bool::from(s_opt.is_some()) {
+            return s_opt.unwrap();
+        }
+
+        let mut sha3 = Sha3::v256();
+        sha3.update(&hash);
+        sha3.finalize(&mut hash);
+    }
+}
+
+fn is_valid_point(point: &G1Affine) -> bool {
+    !bool::from(point.is_identity()) && bool::from(point.is_on_curve() & point.is_torsion_free())
+}
+
+/// `MlsagSignature::verify` as it was before the generator table
+fn old_verify(mlsag: &MlsagSignature, msg: &[u8], public_commitments: &[G1Affine]) -> bool {
+    #[allow(non_snake_case)]
+    let G1 = G1Projective::generator();
+
+    let points_valid = [mlsag.key_image, mlsag.pseudo_commitment]
+        .iter()
+        .chain(mlsag.ring.iter().map(|(pk, _)| pk))
+        .all(is_valid_point);
+    let hidden_commitments_valid =
+        mlsag
+            .ring
+            .iter()
+            .zip(public_commitments)
+            .all(|((_, hidden_commitment), commitment)| {
+                *hidden_commitment
+                    == (G1Projective::from(commitment) - mlsag.pseudo_commitment).to_affine()
+            });
+    if !points_valid || !hidden_commitments_valid {
+        return false;
+    }
+
+    let mut cprime = vec![Scalar::zero(); mlsag.ring.len()];
+    cprime[0] = mlsag.c0;
+
+    for (n, keys) in mlsag.ring.iter().enumerate() {
+        cprime[(n + 1) % mlsag.ring.len()] = hash_to_scalar(&[
+            msg,
+            &(G1 * mlsag.r[n].0 + keys.0 * cprime[n]).to_compressed(),
+            &(G1 * mlsag.r[n].1 + keys.1 * cprime[n]).to_compressed(),
+            &(hash_to_curve(keys.0.into()) * mlsag.r[n].0 + mlsag.key_image * cprime[n])
+                .to_compressed(),
+        ]);
+    }
+    mlsag.c0 == cprime[0]
+}
+
+fn verify(c: &mut Criterion) {
+    let pc_gens = PedersenGens::default();
+    let mut group = c.benchmark_group("verify");
+    group.sample_size(20);
+
+    for ring_size in RING_SIZES {
+        let (material, revealed_pseudo_commitment) = material(ring_size);
+        let public_commitments = material.commitments(&pc_gens);
+        let mlsag = material.sign(MSG, &revealed_pseudo_commitment, &pc_gens);
+        let clsag = ClsagSignature::sign(&material, MSG, &revealed_pseudo_commitment, &pc_gens);
+
+        group.bench_with_input(BenchmarkId::new("mlsag", ring_size), &ring_size, |b, _| {
+            b.iter(|| mlsag.verify(MSG, &public_commitments).unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("mlsag_old", ring_size),
+            &ring_size,
+            |b, _| b.iter(|| assert!(old_verify(&mlsag, MSG, &public_commitments))),
+        );
+        group.bench_with_input(BenchmarkId::new("clsag", ring_size), &ring_size, |b, _| {
+            b.iter(|| clsag.verify(MSG, &public_commitments).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, verify);
+criterion_main!(benches);
@@ -0,0 +1,290 @@
+//! Batch verification of many `RingCtTransaction`s.
+//!
+//! Every linear check, i.e. that each hidden commitment in an MLSAG ring is
+//! `C - C'` and that each transaction balances, is folded into a single
+//! multi-scalar multiplication: each equation `E_i == 0` is weighted by a
+//! scalar `w_i` and we check `sum(w_i * E_i) == 0`.
+//!
+//! The ring signature hash chains are checked one by one, each round hashes
+//! the points of the round before it so there is no equation to fold. Range
+//! proofs are also verified per transaction: folding their final check in here
+//! needs the generators and verification scalars that `bulletproofs` keeps
+//! private, so that has to come from a batch verifier in that crate.
+//!
+//! The weights are derived by hashing the whole batch, so they can not be
+//! known before the transactions are fixed.
+
+use std::collections::BTreeSet;
+
+use blstrs::{
+    group::{ff::Field, Group, GroupEncoding},
+    G1Affine, G1Projective, Scalar,
+};
+use tiny_keccak::{Hasher, Sha3};
+
+use crate::{
+    hash_cache::HashToCurveCache, mlsag::hash_to_scalar, ringct::RingCtTransaction, Error, Result,
+    RingCtMaterial,
+};
+
+const BATCH_WEIGHT_DOMAIN: &[u8] = b"blst-ringct-batch-weight";
+
+/// Verifies every transaction in `batch`, each paired with the public
+/// commitments of its rings as taken by `RingCtTransaction::verify`.
+///
+/// On failure `Error::InvalidTransactionsInBatch` lists the index of every
+/// invalid transaction.
+pub fn verify_batch(batch: &[(&RingCtTransaction, &[Vec<G1Affine>])]) -> Result<()> {
+    verify_batch_with(batch, None)
+}
+
+/// Like `verify_batch`, looking up the hashes of ring members in `cache`.
+/// Decoys shared between the transactions of a batch are then hashed once.
+pub fn verify_batch_with_cache(
+    batch: &[(&RingCtTransaction, &[Vec<G1Affine>])],
+    cache: &HashToCurveCache,
+) -> Result<()> {
+    verify_batch_with(batch, Some(cache))
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(transactions = batch.len()))
+)]
+fn verify_batch_with(
+    batch: &[(&RingCtTransaction, &[Vec<G1Affine>])],
+    cache: Option<&HashToCurveCache>,
+) -> Result<()> {
+    let mut invalid: BTreeSet<usize> = Default::default();
+    let mut terms = LinearTerms::new(batch_seed(batch));
+
+    for (i, (tx, public_commitments_per_ring)) in batch.iter().enumerate() {
+        if verify_non_linear(tx, public_commitments_per_ring, cache).is_err() {
+            invalid.insert(i);
+        } else {
+            terms.push_transaction(tx, public_commitments_per_ring);
+        }
+    }
+
+    if !bool::from(terms.evaluate().is_identity()) {
+        // The combined check only tells us that something is wrong,
+        // fall back to checking the remaining transactions one by one.
+        for (i, (tx, public_commitments_per_ring)) in batch.iter().enumerate() {
+            if !invalid.contains(&i)
+                && tx
+                    .checks(public_commitments_per_ring, cache)
+                    .any(|check| check.is_err())
+            {
+                invalid.insert(i);
+            }
+        }
+    }
+
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidTransactionsInBatch(
+            invalid.into_iter().collect(),
+        ))
+    }
+}
+
+/// Runs every check of `RingCtTransaction::verify` that is not part of the
+/// combined multi-scalar multiplication.
+fn verify_non_linear(
+    tx: &RingCtTransaction,
+    public_commitments_per_ring: &[Vec<G1Affine>],
+    cache: Option<&HashToCurveCache>,
+) -> Result<()> {
+    tx.validate_structure()?;
+    let rings = tx
+        .mlsags
+        .iter()
+        .map(|m| m.ring.len())
+        .chain(tx.clsags.iter().map(|c| c.ring.len()));
+    if public_commitments_per_ring.len() != tx.mlsags.len() + tx.clsags.len() {
+        return Err(Error::RingCountMismatch {
+            expected: tx.mlsags.len() + tx.clsags.len(),
+            found: public_commitments_per_ring.len(),
+        });
+    }
+    if rings
+        .zip(public_commitments_per_ring)
+        .any(|(ring_len, public_commitments)| ring_len != public_commitments.len())
+    {
+        return Err(Error::ExpectedAPublicCommitmentsForEachRingEntry);
+    }
+    tx.validate_output_commitments()?;
+
+    let msg = tx.gen_message();
+    for mlsag in tx.mlsags.iter() {
+        mlsag.verify_ring(&msg, cache)?;
+    }
+    // A CLSAG rebuilds its hidden commitments from the public commitments,
+    // so it has no linear terms and is verified in full here.
+    for (clsag, public_commitments) in tx
+        .clsags
+        .iter()
+        .zip(&public_commitments_per_ring[tx.mlsags.len()..])
+    {
+        clsag.verify_with(&msg, public_commitments, cache)?;
+    }
+    tx.verify_range_proof()
+}
+
+fn batch_seed(batch: &[(&RingCtTransaction, &[Vec<G1Affine>])]) -> [u8; 32] {
+    let mut sha3 = Sha3::v256();
+    sha3.update(BATCH_WEIGHT_DOMAIN);
+    for (tx, public_commitments_per_ring) in batch.iter() {
+        sha3.update(&tx.hash());
+        for commitment in public_commitments_per_ring.iter().flatten() {
+            sha3.update(commitment.to_bytes().as_ref());
+        }
+    }
+    let mut seed = [0u8; 32];
+    sha3.finalize(&mut seed);
+    seed
+}
+
+/// Accumulates the weighted terms of every linear equation in the batch
+struct LinearTerms {
+    seed: [u8; 32],
+    equations: u64,
+    points: Vec<G1Projective>,
+    scalars: Vec<Scalar>,
+}
+
+impl LinearTerms {
+    fn new(seed: [u8; 32]) -> Self {
+        Self {
+            seed,
+            equations: 0,
+            points: Default::default(),
+            scalars: Default::default(),
+        }
+    }
+
+    fn next_weight(&mut self) -> Scalar {
+        self.equations += 1;
+        hash_to_scalar(&[&self.seed, &self.equations.to_le_bytes()])
+    }
+
+    fn push(&mut self, point: impl Into<G1Projective>, scalar: Scalar) {
+        self.points.push(point.into());
+        self.scalars.push(scalar);
+    }
+
+    fn push_transaction(
+        &mut self,
+        tx: &RingCtTransaction,
+        public_commitments_per_ring: &[Vec<G1Affine>],
+    ) {
+        let rings = tx.mlsags.iter().map(|m| (&m.ring, m.pseudo_commitment));
+
+        // hidden - C + C' == 0, for every member of every MLSAG ring
+        for ((ring, pseudo_commitment), public_commitments) in
+            rings.zip(public_commitments_per_ring)
+        {
+            let mut pseudo_commitment_weight = Scalar::zero();
+            for ((_, hidden_commitment), public_commitment) in ring.iter().zip(public_commitments) {
+                let w = self.next_weight();
+                self.push(*hidden_commitment, w);
+                self.push(*public_commitment, -w);
+                pseudo_commitment_weight += w;
+            }
+            self.push(pseudo_commitment, pseudo_commitment_weight);
+        }
+
+        // sum(C') - sum(outputs) - fee * H == 0
+        let w = self.next_weight();
+        for pseudo_commitment in tx.pseudo_commitments() {
+            self.push(pseudo_commitment, w);
+        }
+        for output in tx.outputs.iter() {
+            self.push(output.commitment(), -w);
+        }
+        self.push(RingCtMaterial::pc_gens().B, -w * Scalar::from(tx.fee));
+    }
+
+    fn evaluate(&self) -> G1Projective {
+        G1Projective::multi_exp(&self.points, &self.scalars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::group::Curve;
+    use rand_core::OsRng;
+
+    use crate::{ringct::tests::test_material, InMemoryLedger, Ledger, RingSignatureScheme};
+
+    use super::*;
+
+    #[test]
+    fn test_verify_batch() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+
+        let txs: Vec<RingCtTransaction> = [
+            RingSignatureScheme::Mlsag,
+            RingSignatureScheme::Clsag,
+            RingSignatureScheme::Mlsag,
+        ]
+        .into_iter()
+        .map(|scheme| {
+            let ring_ct = test_material(&mut ledger, scheme, &mut rng);
+            let (tx, _) = ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+            tx
+        })
+        .collect();
+        let public_commitments: Vec<Vec<Vec<G1Affine>>> = txs
+            .iter()
+            .map(|tx| ledger.public_commitments(tx).unwrap())
+            .collect();
+
+        let batch: Vec<(&RingCtTransaction, &[Vec<G1Affine>])> = txs
+            .iter()
+            .zip(public_commitments.iter())
+            .map(|(tx, pcs)| (tx, pcs.as_slice()))
+            .collect();
+        assert!(verify_batch(&batch).is_ok());
+
+        // Every decoy comes from the same ledger, so the cache is shared between rings
+        let cache = HashToCurveCache::default();
+        assert!(verify_batch_with_cache(&batch, &cache).is_ok());
+        assert!(!cache.is_empty());
+        assert!(txs[0]
+            .verify_with_cache(&public_commitments[0], &cache)
+            .is_ok());
+
+        // Claiming the wrong public commitment for a member of an MLSAG ring
+        // only breaks the combined linear check, the batch must still find
+        // the culprit.
+        let mut wrong_mlsag_commitments = public_commitments[0].clone();
+        wrong_mlsag_commitments[0][0] = G1Projective::random(&mut rng).to_affine();
+        let mut bad_batch = batch.clone();
+        bad_batch[0].1 = &wrong_mlsag_commitments;
+        assert_eq!(
+            verify_batch(&bad_batch),
+            Err(Error::InvalidTransactionsInBatch(vec![0]))
+        );
+
+        // A CLSAG hashes the commitments it is given, so it fails on its own
+        let mut wrong_clsag_commitments = public_commitments[1].clone();
+        wrong_clsag_commitments[0][0] = G1Projective::random(&mut rng).to_affine();
+        bad_batch[1].1 = &wrong_clsag_commitments;
+        assert_eq!(
+            verify_batch(&bad_batch),
+            Err(Error::InvalidTransactionsInBatch(vec![0, 1]))
+        );
+
+        // A corrupted range proof is caught before the combined check
+        let mut bad_tx = txs[2].clone();
+        bad_tx.range_proof = txs[0].range_proof.clone();
+        bad_batch[2].0 = &bad_tx;
+        assert_eq!(
+            verify_batch(&bad_batch),
+            Err(Error::InvalidTransactionsInBatch(vec![0, 1, 2]))
+        );
+    }
+}
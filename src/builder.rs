@@ -0,0 +1,222 @@
+//! Builds and signs a transaction from spendable inputs and payments.
+//!
+//! `TransactionBuilder` selects enough inputs to cover the payments and the
+//! fee, pays whatever is left back to the sender as change, fetches decoys for
+//! every input from a ledger and signs the result.
+
+use rand_core::{CryptoRng, RngCore};
+
+use crate::{
+    decoy::uniform_index,
+    ringct::{checked_sum, RingCtTransaction},
+    Error, Ledger, MlsagMaterial, Result, RevealedCommitment, RingCtMaterial, RingSignatureScheme,
+    StealthAddress, TransactionKey, TrueInput,
+};
+
+/// The ring size used unless `TransactionBuilder::ring_size` is called
+pub const DEFAULT_RING_SIZE: usize = 11;
+
+pub struct TransactionBuilder {
+    change_address: StealthAddress,
+    inputs: Vec<TrueInput>,
+    payments: Vec<(StealthAddress, u64)>,
+    ring_size: usize,
+    fee: u64,
+    scheme: RingSignatureScheme,
+}
+
+impl TransactionBuilder {
+    /// Any change is paid back to `change_address`
+    pub fn new(change_address: StealthAddress) -> Self {
+        Self {
+            change_address,
+            inputs: Default::default(),
+            payments: Default::default(),
+            ring_size: DEFAULT_RING_SIZE,
+            fee: 0,
+            scheme: Default::default(),
+        }
+    }
+
+    /// Adds an output the transaction may spend
+    pub fn add_input(mut self, input: TrueInput) -> Self {
+        self.inputs.push(input);
+        self
+    }
+
+    pub fn add_inputs(mut self, inputs: impl IntoIterator<Item = TrueInput>) -> Self {
+        self.inputs.extend(inputs);
+        self
+    }
+
+    /// Pays `amount` to `address`
+    pub fn add_payment(mut self, address: StealthAddress, amount: u64) -> Self {
+        self.payments.push((address, amount));
+        self
+    }
+
+    /// The number of members in each ring, the true input included
+    pub fn ring_size(mut self, ring_size: usize) -> Self {
+        self.ring_size = ring_size;
+        self
+    }
+
+    pub fn fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    pub fn scheme(mut self, scheme: RingSignatureScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Selects inputs, adds change, fetches decoys from `ledger` and signs.
+    ///
+    /// Inputs are picked largest first until they cover the payments and the
+    /// fee, unpicked inputs are left unspent. Outputs are placed in random
+    /// order so the change output can not be told apart by its position.
+    pub fn build(
+        self,
+        ledger: &impl Ledger,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(RingCtTransaction, Vec<RevealedCommitment>)> {
+        let needed = checked_sum(
+            self.payments
+                .iter()
+                .map(|(_, amount)| *amount)
+                .chain([self.fee]),
+        )?;
+
+        let mut inputs = self.inputs;
+        inputs.sort_by_key(|input| std::cmp::Reverse(input.revealed_commitment.value));
+        let mut selected = Vec::new();
+        let mut total = 0;
+        for input in inputs {
+            if total >= needed && !selected.is_empty() {
+                break;
+            }
+            total = checked_sum([total, input.revealed_commitment.value])?;
+            selected.push(input);
+        }
+        if total < needed {
+            return Err(Error::InsufficientFunds);
+        }
+
+        let mut payments = self.payments;
+        if total > needed {
+            payments.push((self.change_address, total - needed));
+        }
+        for i in (1..payments.len()).rev() {
+            payments.swap(i, uniform_index(i + 1, &mut *rng));
+        }
+
+        let tx_key = TransactionKey::random(&mut *rng);
+        let outputs = payments
+            .iter()
+            .enumerate()
+            .map(|(i, (address, amount))| tx_key.output(address, i, *amount))
+            .collect();
+
+        let decoy_count = self.ring_size.saturating_sub(1);
+        let inputs = selected
+            .into_iter()
+            .map(|input| MlsagMaterial::from_ledger(input, ledger, decoy_count, &mut *rng))
+            .collect::<Result<_>>()?;
+
+        RingCtMaterial {
+            inputs,
+            outputs,
+            fee: self.fee,
+            tx_public_key: Some(tx_key.public_key()),
+            scheme: self.scheme,
+        }
+        .sign(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::{
+        group::{ff::Field, Curve, Group},
+        G1Projective, Scalar,
+    };
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::{InMemoryLedger, StealthSecretKeys, Wallet};
+
+    #[test]
+    fn test_transaction_builder() {
+        let mut rng = OsRng;
+        let pc_gens = RingCtMaterial::pc_gens();
+        let mut ledger = InMemoryLedger::default();
+        for _ in 0..10 {
+            ledger.log(
+                G1Projective::random(&mut rng).to_affine(),
+                G1Projective::random(&mut rng).to_affine(),
+            );
+        }
+
+        let inputs = Vec::from_iter([3, 10, 4].into_iter().map(|value| TrueInput {
+            secret_key: Scalar::random(&mut rng),
+            revealed_commitment: RevealedCommitment::from_value(value, &mut rng),
+        }));
+        for input in inputs.iter() {
+            ledger.log(
+                input.public_key().to_affine(),
+                input.revealed_commitment.commit(&pc_gens).to_affine(),
+            );
+        }
+
+        let mut sender = Wallet::new(StealthSecretKeys::random(&mut rng));
+        let mut recipient = Wallet::new(StealthSecretKeys::random(&mut rng));
+
+        let (tx, revealed_output_commitments) = TransactionBuilder::new(sender.address())
+            .add_inputs(inputs.clone())
+            .add_payment(recipient.address(), 5)
+            .ring_size(4)
+            .fee(1)
+            .build(&ledger, &mut rng)
+            .expect("Failed to build transaction");
+        assert!(tx.verify_with_ledger(&ledger).is_ok());
+
+        // The largest input covers 5 + 1 on its own, leaving 4 in change
+        assert_eq!(tx.mlsags.len(), 1);
+        assert_eq!(tx.mlsags[0].public_keys().len(), 4);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(
+            revealed_output_commitments
+                .iter()
+                .map(RevealedCommitment::value)
+                .sum::<u64>(),
+            9
+        );
+
+        recipient.scan(&tx);
+        sender.scan(&tx);
+        assert_eq!(recipient.balance(), Ok(5));
+        assert_eq!(sender.balance(), Ok(4));
+
+        // Spending everything leaves no change
+        let (tx, _) = TransactionBuilder::new(sender.address())
+            .add_inputs(inputs.clone())
+            .add_payment(recipient.address(), 15)
+            .fee(2)
+            .ring_size(3)
+            .scheme(RingSignatureScheme::Clsag)
+            .build(&ledger, &mut rng)
+            .expect("Failed to build transaction");
+        assert!(tx.verify_with_ledger(&ledger).is_ok());
+        assert_eq!(tx.clsags.len(), 3);
+        assert_eq!(tx.outputs.len(), 1);
+
+        assert!(matches!(
+            TransactionBuilder::new(sender.address())
+                .add_inputs(inputs)
+                .add_payment(recipient.address(), 18)
+                .build(&ledger, &mut rng),
+            Err(Error::InsufficientFunds)
+        ));
+    }
+}
@@ -0,0 +1,246 @@
+//! Strategies for picking the decoys of a ring.
+//!
+//! A ring only hides the true input if its decoys look like outputs someone
+//! would plausibly spend. Real spends favour recent outputs, so a selector
+//! that ignores age makes the true input stand out.
+//!
+//! Outputs are identified by their age: 0 is the newest output of the ledger
+//! and `num_outputs - 1` the oldest.
+
+use std::{collections::BTreeSet, fmt};
+
+use rand_core::RngCore;
+
+// Attempts per decoy before falling back to a uniform pick, so that a
+// distribution that rarely lands inside the ledger can not stall selection
+const MAX_ATTEMPTS_PER_DECOY: usize = 100;
+
+pub trait DecoySelector: fmt::Debug + Send + Sync {
+    /// Samples the age of a decoy from a ledger of `num_outputs` outputs.
+    /// Ages of `num_outputs` or more are rejected and sampled again.
+    fn sample_age(&self, num_outputs: usize, rng: &mut dyn RngCore) -> usize;
+
+    /// Picks up to `n` distinct outputs, none of them in `exclude`, and returns
+    /// their positions in order of insertion, 0 being the oldest output.
+    fn select(
+        &self,
+        num_outputs: usize,
+        n: usize,
+        exclude: &BTreeSet<usize>,
+        rng: &mut dyn RngCore,
+    ) -> Vec<usize> {
+        let available = num_outputs - exclude.range(..num_outputs).count();
+        let n = n.min(available);
+
+        let mut chosen: Vec<usize> = Vec::with_capacity(n);
+        for _ in 0..n * MAX_ATTEMPTS_PER_DECOY {
+            if chosen.len() == n {
+                break;
+            }
+            let age = self.sample_age(num_outputs, rng);
+            if age >= num_outputs {
+                continue;
+            }
+            let position = num_outputs - 1 - age;
+            if !exclude.contains(&position) && !chosen.contains(&position) {
+                chosen.push(position);
+            }
+        }
+
+        if chosen.len() < n {
+            // e.g. a young ledger under a distribution tuned for a large one
+            let mut rest: Vec<usize> = (0..num_outputs)
+                .filter(|i| !exclude.contains(i) && !chosen.contains(i))
+                .collect();
+            while chosen.len() < n {
+                chosen.push(rest.swap_remove(uniform_index(rest.len(), rng)));
+            }
+        }
+        chosen
+    }
+}
+
+/// Every output is equally likely to be picked
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UniformSelector;
+
+impl DecoySelector for UniformSelector {
+    fn sample_age(&self, num_outputs: usize, rng: &mut dyn RngCore) -> usize {
+        uniform_index(num_outputs, rng)
+    }
+}
+
+/// Picks decoys the way Monero does: the log of a decoy's age follows a gamma
+/// distribution, so most decoys are recent while old outputs are still picked.
+///
+/// Monero measures age in seconds, `seconds_per_output` converts it to an age
+/// in outputs.
+#[derive(Debug, Clone, Copy)]
+pub struct GammaSelector {
+    pub shape: f64,
+    pub rate: f64,
+    pub seconds_per_output: f64,
+}
+
+impl Default for GammaSelector {
+    /// Monero's parameters, assuming one output every two minutes
+    fn default() -> Self {
+        Self {
+            shape: 19.28,
+            rate: 1.61,
+            seconds_per_output: 120.0,
+        }
+    }
+}
+
+impl DecoySelector for GammaSelector {
+    fn sample_age(&self, num_outputs: usize, rng: &mut dyn RngCore) -> usize {
+        let seconds = sample_gamma(self.shape, self.rate, rng).exp();
+        let age = seconds / self.seconds_per_output;
+        if age < num_outputs as f64 {
+            age as usize
+        } else {
+            num_outputs
+        }
+    }
+}
+
+/// With probability `recent_probability` a decoy is picked uniformly from the
+/// `window` newest outputs, otherwise uniformly from the whole ledger.
+#[derive(Debug, Clone, Copy)]
+pub struct RecentBiasedSelector {
+    pub window: usize,
+    pub recent_probability: f64,
+}
+
+impl Default for RecentBiasedSelector {
+    fn default() -> Self {
+        Self {
+            window: 100,
+            recent_probability: 0.5,
+        }
+    }
+}
+
+impl DecoySelector for RecentBiasedSelector {
+    fn sample_age(&self, num_outputs: usize, rng: &mut dyn RngCore) -> usize {
+        if uniform_f64(rng) < self.recent_probability {
+            uniform_index(self.window.clamp(1, num_outputs.max(1)), rng)
+        } else {
+            uniform_index(num_outputs, rng)
+        }
+    }
+}
+
+/// A uniform index in 0..len, without modulo bias. Returns 0 when `len` is 0.
+pub(crate) fn uniform_index(len: usize, rng: &mut dyn RngCore) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    let len = len as u64;
+    let zone = u64::MAX - (u64::MAX - len + 1) % len;
+    loop {
+        let x = rng.next_u64();
+        if x <= zone {
+            return (x % len) as usize;
+        }
+    }
+}
+
+/// A uniform float in [0, 1)
+fn uniform_f64(rng: &mut dyn RngCore) -> f64 {
+    (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// A standard normal sample, by the Box-Muller transform
+fn sample_normal(rng: &mut dyn RngCore) -> f64 {
+    let u1 = 1.0 - uniform_f64(rng); // (0, 1], so the log is finite
+    let u2 = uniform_f64(rng);
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// A Gamma(shape, rate) sample, by Marsaglia and Tsang's method
+fn sample_gamma(shape: f64, rate: f64, rng: &mut dyn RngCore) -> f64 {
+    if shape < 1.0 {
+        // Gamma(k) = Gamma(k + 1) * U^(1/k)
+        let u = 1.0 - uniform_f64(rng);
+        return sample_gamma(shape + 1.0, rate, rng) * u.powf(1.0 / shape);
+    }
+
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let x = sample_normal(rng);
+        let v = (1.0 + c * x).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+        let u = 1.0 - uniform_f64(rng);
+        if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v / rate;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn mean_age(selector: &dyn DecoySelector, num_outputs: usize) -> f64 {
+        let samples = 1000;
+        let mut ages = 0;
+        for _ in 0..samples {
+            for position in selector.select(num_outputs, 1, &BTreeSet::new(), &mut OsRng) {
+                ages += num_outputs - 1 - position;
+            }
+        }
+        ages as f64 / samples as f64
+    }
+
+    #[test]
+    fn test_decoy_selectors() {
+        let selectors: Vec<Box<dyn DecoySelector>> = vec![
+            Box::new(UniformSelector),
+            Box::new(GammaSelector::default()),
+            Box::new(RecentBiasedSelector::default()),
+        ];
+
+        for selector in selectors.iter() {
+            // Picks are distinct and avoid excluded outputs
+            let exclude = BTreeSet::from([0, 3, 9]);
+            let chosen = selector.select(10, 5, &exclude, &mut OsRng);
+            assert_eq!(chosen.len(), 5);
+            assert_eq!(BTreeSet::from_iter(chosen.iter()).len(), 5);
+            assert!(chosen.iter().all(|i| *i < 10 && !exclude.contains(i)));
+
+            // A small ledger gives everything it has
+            let chosen = selector.select(10, 20, &exclude, &mut OsRng);
+            assert_eq!(chosen.len(), 7);
+            assert!(selector
+                .select(0, 2, &BTreeSet::new(), &mut OsRng)
+                .is_empty());
+        }
+
+        // Uniform picks average half the ledger's age, the others favour recent outputs
+        let num_outputs = 100_000;
+        let uniform = mean_age(&UniformSelector, num_outputs);
+        assert!((uniform - num_outputs as f64 / 2.0).abs() < num_outputs as f64 / 10.0);
+        assert!(mean_age(&GammaSelector::default(), num_outputs) < uniform / 2.0);
+        assert!(mean_age(&RecentBiasedSelector::default(), num_outputs) < uniform * 0.75);
+    }
+
+    #[test]
+    fn test_sample_gamma() {
+        // The mean of Gamma(k, rate) is k / rate
+        for (shape, rate) in [(19.28, 1.61), (0.5, 2.0)] {
+            let n = 20_000;
+            let mean = (0..n)
+                .map(|_| sample_gamma(shape, rate, &mut OsRng))
+                .sum::<f64>()
+                / n as f64;
+            assert!((mean - shape / rate).abs() < 0.05 * shape / rate);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use blstrs::{G1Affine, Scalar};
+
+use crate::{Error, Result};
+
+pub(crate) const G1_COMPRESSED_LEN: usize = 48;
+pub(crate) const SCALAR_LEN: usize = 32;
+
+/// Encodes an optional point as a presence flag followed by the point when present,
+/// see `Reader::read_optional_g1`.
+pub(crate) fn optional_g1_bytes(point: &Option<G1Affine>) -> Vec<u8> {
+    match point {
+        Some(point) => [&[1u8][..], &point.to_compressed()].concat(),
+        None => vec![0u8],
+    }
+}
+
+/// A cursor over a byte slice used when decoding our wire formats.
+/// Every read checks that enough input remains and that the bytes decode
+/// to a valid curve point or canonical scalar.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub(crate) fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(Error::TruncatedInput);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    /// Reads a u32 length prefix and checks that the input could hold at least
+    /// `len * min_item_len` more bytes, so a corrupt length can not trigger a
+    /// huge allocation.
+    pub(crate) fn read_len(&mut self, min_item_len: usize) -> Result<usize> {
+        let len = self.read_u32()? as usize;
+        match len.checked_mul(min_item_len) {
+            Some(needed) if needed <= self.remaining() => Ok(len),
+            _ => Err(Error::TruncatedInput),
+        }
+    }
+
+    pub(crate) fn read_scalar(&mut self) -> Result<Scalar> {
+        let bytes = self.read_array::<SCALAR_LEN>()?;
+        Option::from(Scalar::from_bytes_le(&bytes)).ok_or(Error::InvalidScalarEncoding)
+    }
+
+    /// Reads a compressed G1 point, checking that it is on the curve and in the
+    /// prime order subgroup.
+    pub(crate) fn read_g1(&mut self) -> Result<G1Affine> {
+        let bytes = self.read_array::<G1_COMPRESSED_LEN>()?;
+        Option::from(G1Affine::from_compressed(&bytes)).ok_or(Error::InvalidG1PointEncoding)
+    }
+
+    /// Reads a presence flag (0 or 1) followed by the point when present.
+    pub(crate) fn read_optional_g1(&mut self) -> Result<Option<G1Affine>> {
+        match self.read_array::<1>()?[0] {
+            0 => Ok(None),
+            1 => Ok(Some(self.read_g1()?)),
+            flag => Err(Error::InvalidOptionFlag(flag)),
+        }
+    }
+
+    /// Fails if any input is left over once decoding is finished.
+    pub(crate) fn finish(self) -> Result<()> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::TrailingBytes)
+        }
+    }
+}
@@ -8,10 +8,103 @@ pub enum Error {
     InvalidHiddenCommitmentInRing,
     #[error("InputPseudoCommitmentsDoNotSumToOutputCommitments")]
     InputPseudoCommitmentsDoNotSumToOutputCommitments,
+    #[error("A transaction needs at least one input")]
+    NoInputs,
+    #[error("A transaction needs at least one output")]
+    NoOutputs,
+    #[error("A transaction has more outputs than the maximum")]
+    TooManyOutputs,
+    #[error("A ring has fewer members than the minimum ring size")]
+    RingTooSmall,
+    #[error("The signing nonces of an input do not match the size of its ring")]
+    MismatchedMaterial,
+    #[error("The sum of the amounts overflows a u64")]
+    AmountOverflow,
+    #[error("The input amounts do not equal the output amounts plus the fee")]
+    InputsAndOutputsDoNotBalance,
+    #[error("The inputs do not cover the payments and the fee")]
+    InsufficientFunds,
+    #[error("Expected public commitments for {expected} rings, found {found}")]
+    RingCountMismatch { expected: usize, found: usize },
+    #[error("Input {index} is invalid: {source}")]
+    InvalidInput { index: usize, source: Box<Error> },
+    #[error("Ring member {index} is invalid: {source}")]
+    InvalidRingMember { index: usize, source: Box<Error> },
+    #[error("Output {index} is invalid: {source}")]
+    InvalidOutput { index: usize, source: Box<Error> },
+    #[error("A ring member is not in the ledger")]
+    UnknownRingMember,
+    #[error("The ledger does not hold enough outputs to use as decoys")]
+    NotEnoughDecoys,
     #[error("The MLSAG ring signature is not valid")]
     InvalidRingSignature,
     #[error("KeyImage is not on the BLS12-381 G1 Curve")]
     KeyImageNotOnCurve,
+    #[error("KeyImage is the identity point")]
+    KeyImageIsIdentity,
+    #[error("KeyImage is not in the prime order subgroup of G1")]
+    KeyImageNotInSubgroup,
+    #[error("A public key in the ring is the identity point")]
+    RingPublicKeyIsIdentity,
+    #[error("A public key in the ring is not in the prime order subgroup of G1")]
+    RingPublicKeyNotInSubgroup,
+    #[error("A pseudo commitment is the identity point")]
+    PseudoCommitmentIsIdentity,
+    #[error("A pseudo commitment is not in the prime order subgroup of G1")]
+    PseudoCommitmentNotInSubgroup,
+    #[error("An output commitment is the identity point")]
+    OutputCommitmentIsIdentity,
+    #[error("An output commitment is not in the prime order subgroup of G1")]
+    OutputCommitmentNotInSubgroup,
+    #[error("Input ended before decoding was complete")]
+    TruncatedInput,
+    #[error("Unexpected bytes remain after decoding")]
+    TrailingBytes,
+    #[error("The transactions at these batch indices are invalid: {0:?}")]
+    InvalidTransactionsInBatch(Vec<usize>),
+    #[error("Encoded transaction does not start with the expected magic bytes")]
+    InvalidWireMagic,
+    #[error("Unsupported transaction encoding version: {0}")]
+    UnsupportedWireVersion(u16),
+    #[error("Bytes do not encode a valid point in the BLS12-381 G1 group")]
+    InvalidG1PointEncoding,
+    #[error("Bytes do not encode a canonical BLS12-381 scalar")]
+    InvalidScalarEncoding,
+    #[error("Invalid presence flag for an optional field: {0}")]
+    InvalidOptionFlag(u8),
+    #[error("The output does not carry an encrypted amount")]
+    MissingEncryptedAmount,
+    #[error("The decrypted amount does not open the output commitment")]
+    EncryptedAmountDoesNotOpenCommitment,
+    #[error("A key image has already been spent")]
+    KeyImageAlreadySpent,
+    #[error("IO Error: {0}")]
+    Io(String),
+    #[error("The spent key image file is corrupt at byte {0}")]
+    CorruptSpentKeyImages(u64),
     #[error("BulletProofs Error: {0}")]
     BulletProofs(#[from] bulletproofs::ProofError),
 }
+
+impl Error {
+    pub(crate) fn at_input(self, index: usize) -> Self {
+        Error::InvalidInput {
+            index,
+            source: Box::new(self),
+        }
+    }
+
+    pub(crate) fn at_ring_member(self, index: usize) -> Self {
+        Error::InvalidRingMember {
+            index,
+            source: Box::new(self),
+        }
+    }
+
+    pub(crate) fn at_output(self, index: usize) -> Self {
+        Error::InvalidOutput {
+            index,
+            source: Box::new(self),
+        }
+    }
+}
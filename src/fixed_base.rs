@@ -0,0 +1,73 @@
+//! Fixed-base scalar multiplication by the G1 generator.
+//!
+//! Verification multiplies G by a fresh scalar for every ring member. A table
+//! holding j 16^i G for every 4 bit window i and digit j turns each of those
+//! products into at most 64 mixed additions, with no doublings. The table is
+//! built on first use and shared between threads.
+//!
+//! Which table entries are read depends on the scalar, so this must only be
+//! used with public scalars such as those of a signature, never with secrets.
+
+use std::sync::OnceLock;
+
+use blstrs::{
+    group::{prime::PrimeCurveAffine, Curve, Group},
+    G1Affine, G1Projective, Scalar,
+};
+
+const WINDOW_BITS: usize = 4;
+const WINDOWS: usize = 256 / WINDOW_BITS;
+const DIGITS: usize = 1 << WINDOW_BITS;
+
+static GENERATOR_TABLE: OnceLock<Vec<[G1Affine; DIGITS]>> = OnceLock::new();
+
+fn generator_table() -> &'static [[G1Affine; DIGITS]] {
+    GENERATOR_TABLE.get_or_init(|| {
+        let mut base = G1Projective::generator(); // 16^i G
+        (0..WINDOWS)
+            .map(|_| {
+                let mut row = [G1Affine::identity(); DIGITS];
+                let mut multiple = G1Projective::identity();
+                for entry in row.iter_mut().skip(1) {
+                    multiple += base;
+                    *entry = multiple.to_affine();
+                }
+                base = multiple + base;
+                row
+            })
+            .collect()
+    })
+}
+
+/// s G, for a public scalar s
+pub(crate) fn mul_generator(scalar: &Scalar) -> G1Projective {
+    let table = generator_table();
+    let mut acc = G1Projective::identity();
+    for (i, byte) in scalar.to_bytes_le().iter().enumerate() {
+        for (window, digit) in [(2 * i, byte & 0x0f), (2 * i + 1, byte >> 4)] {
+            if digit != 0 {
+                acc += table[window][digit as usize];
+            }
+        }
+    }
+    acc
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::group::ff::Field;
+    use rand_core::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn test_mul_generator() {
+        let g = G1Projective::generator();
+        for scalar in [Scalar::zero(), Scalar::one(), -Scalar::one()]
+            .into_iter()
+            .chain((0..10).map(|_| Scalar::random(OsRng)))
+        {
+            assert_eq!(mul_generator(&scalar), g * scalar);
+        }
+    }
+}
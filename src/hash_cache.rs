@@ -0,0 +1,158 @@
+//! A cache of `hash_to_curve` over ring public keys.
+//!
+//! Every signature hashes each ring member's public key to the curve, and
+//! popular decoys appear in many rings. `HashToCurveCache` keeps the most
+//! recently used hashes so they are computed once. It is safe to share one
+//! cache between threads.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use blstrs::{G1Affine, G1Projective};
+
+/// The capacity of `HashToCurveCache::default()`
+pub const DEFAULT_CACHE_CAPACITY: usize = 4096;
+
+/// Bounded cache of `hash_to_curve`, evicting the least recently used entry
+pub struct HashToCurveCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    hashes: BTreeMap<[u8; 48], (G1Projective, u64)>, // compressed point -> (hash, last use)
+    recency: BTreeMap<u64, [u8; 48]>,                // last use -> compressed point
+    clock: u64,
+}
+
+impl Default for HashToCurveCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl HashToCurveCache {
+    /// A cache holding at most `capacity` hashes, 0 disables caching
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Default::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// `crate::hash_to_curve(point)`, from the cache when possible
+    pub fn hash_to_curve(&self, point: &G1Affine) -> G1Projective {
+        let key = point.to_compressed();
+        if let Some(hash) = self.entries().get(&key) {
+            return hash;
+        }
+
+        // Hash without holding the lock, other threads may use the cache meanwhile
+        let hash = crate::hash_to_curve(point.into());
+        if self.capacity > 0 {
+            self.entries().insert(key, hash, self.capacity);
+        }
+        hash
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        // The entries are consistent between operations, so a panic in
+        // another thread does not make them unusable
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Entries {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, key: &[u8; 48]) -> Option<G1Projective> {
+        let now = self.tick();
+        let (hash, last_use) = self.hashes.get_mut(key)?;
+        self.recency.remove(last_use);
+        self.recency.insert(now, *key);
+        *last_use = now;
+        Some(*hash)
+    }
+
+    fn insert(&mut self, key: [u8; 48], hash: G1Projective, capacity: usize) {
+        let now = self.tick();
+        if let Some((_, last_use)) = self.hashes.insert(key, (hash, now)) {
+            // Another thread inserted it while we were hashing
+            self.recency.remove(&last_use);
+        }
+        self.recency.insert(now, key);
+
+        while self.hashes.len() > capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.hashes.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Hp(P), through `cache` when there is one
+pub(crate) fn hash_to_curve_with(
+    cache: Option<&HashToCurveCache>,
+    point: &G1Affine,
+) -> G1Projective {
+    match cache {
+        Some(cache) => cache.hash_to_curve(point),
+        None => crate::hash_to_curve(point.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::group::{Curve, Group};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn test_hash_to_curve_cache() {
+        let points = Vec::from_iter((0..4).map(|_| G1Projective::random(OsRng).to_affine()));
+        let cache = HashToCurveCache::new(2);
+
+        for point in points[..2].iter() {
+            assert_eq!(
+                cache.hash_to_curve(point),
+                crate::hash_to_curve(point.into())
+            );
+        }
+        assert_eq!(cache.len(), 2);
+
+        // Using points[0] makes points[1] the least recently used
+        cache.hash_to_curve(&points[0]);
+        cache.hash_to_curve(&points[2]);
+        assert_eq!(cache.len(), 2);
+        let entries = cache.entries();
+        assert!(entries.hashes.contains_key(&points[0].to_compressed()));
+        assert!(!entries.hashes.contains_key(&points[1].to_compressed()));
+        drop(entries);
+
+        let disabled = HashToCurveCache::new(0);
+        assert_eq!(
+            disabled.hash_to_curve(&points[3]),
+            crate::hash_to_curve(points[3].into())
+        );
+        assert!(disabled.is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+//! A deterministic RNG used for seed driven signing.
+//!
+//! The inputs are absorbed into a SHA3 key which is then expanded in counter
+//! mode: block_i = SHA3(key || i). The same inputs always give the same stream.
+
+use rand_core::{CryptoRng, RngCore};
+use tiny_keccak::{Hasher, Sha3};
+
+const BLOCK_LEN: usize = 32;
+
+pub(crate) struct HashRng {
+    key: [u8; 32],
+    counter: u64,
+    block: [u8; BLOCK_LEN],
+    pos: usize,
+}
+
+impl HashRng {
+    /// Seeds the RNG from a domain separator and a list of inputs.
+    /// Each input is length prefixed so that distinct lists never collide.
+    pub(crate) fn new(domain: &[u8], inputs: &[&[u8]]) -> Self {
+        let mut sha3 = Sha3::v256();
+        sha3.update(&(domain.len() as u64).to_le_bytes());
+        sha3.update(domain);
+        for input in inputs {
+            sha3.update(&(input.len() as u64).to_le_bytes());
+            sha3.update(input);
+        }
+        let mut key = [0u8; 32];
+        sha3.finalize(&mut key);
+
+        Self {
+            key,
+            counter: 0,
+            block: [0u8; BLOCK_LEN],
+            pos: BLOCK_LEN,
+        }
+    }
+
+    fn refill(&mut self) {
+        let mut sha3 = Sha3::v256();
+        sha3.update(&self.key);
+        sha3.update(&self.counter.to_le_bytes());
+        sha3.finalize(&mut self.block);
+        self.counter += 1;
+        self.pos = 0;
+    }
+}
+
+impl RngCore for HashRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest.iter_mut() {
+            if self.pos == BLOCK_LEN {
+                self.refill();
+            }
+            *byte = self.block[self.pos];
+            self.pos += 1;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for HashRng {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_rng_is_deterministic() {
+        let mut a = HashRng::new(b"domain", &[b"seed"]);
+        let mut b = HashRng::new(b"domain", &[b"seed"]);
+        let mut c = HashRng::new(b"domain", &[b"se", b"ed"]);
+
+        let (mut x, mut y, mut z) = ([0u8; 100], [0u8; 100], [0u8; 100]);
+        a.fill_bytes(&mut x);
+        b.fill_bytes(&mut y);
+        c.fill_bytes(&mut z);
+
+        assert_eq!(x, y);
+        assert_ne!(x, z);
+        assert_ne!(x[..BLOCK_LEN], x[BLOCK_LEN..2 * BLOCK_LEN]);
+    }
+}
@@ -0,0 +1,176 @@
+//! The view of the ledger needed to build and verify transactions.
+//!
+//! Rings reference earlier outputs only by public key, so signers need the
+//! ledger to pick decoys and verifiers need it to resolve the commitment of
+//! every ring member. The ledger also tracks which key images have been spent.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use blstrs::G1Affine;
+use rand_core::RngCore;
+
+use crate::{
+    decoy::{DecoySelector, UniformSelector},
+    ringct::RingCtTransaction,
+    DecoyInput, Error, Result,
+};
+
+pub trait Ledger {
+    /// Records an output so it can be used as a ring member
+    fn log(&mut self, public_key: G1Affine, commitment: G1Affine);
+
+    /// The commitment of the output with `public_key`
+    fn lookup(&self, public_key: &G1Affine) -> Option<G1Affine>;
+
+    /// Returns up to `n` distinct outputs to use as decoys, none of them in `exclude`
+    fn fetch_decoys(
+        &self,
+        n: usize,
+        exclude: &[G1Affine],
+        rng: &mut dyn RngCore,
+    ) -> Vec<DecoyInput>;
+
+    fn is_spent(&self, key_image: &G1Affine) -> bool;
+
+    fn mark_spent(&mut self, key_image: G1Affine);
+
+    /// Resolves the public commitments of every ring in `tx`, in the form
+    /// taken by `RingCtTransaction::verify`.
+    fn public_commitments(&self, tx: &RingCtTransaction) -> Result<Vec<Vec<G1Affine>>> {
+        tx.ring_public_keys()
+            .iter()
+            .map(|public_keys| {
+                public_keys
+                    .iter()
+                    .map(|pk| self.lookup(pk).ok_or(Error::UnknownRingMember))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// A `Ledger` held in memory, outputs are kept in the order they were logged.
+/// Decoys are picked by a `DecoySelector`, uniformly unless set otherwise.
+#[derive(Debug, Clone)]
+pub struct InMemoryLedger {
+    outputs: Vec<(G1Affine, G1Affine)>, // (public key, commitment)
+    index: BTreeMap<[u8; 48], usize>,   // compressed public key -> position in outputs
+    spent: BTreeSet<[u8; 48]>,          // compressed key images
+    selector: Arc<dyn DecoySelector>,
+}
+
+impl Default for InMemoryLedger {
+    fn default() -> Self {
+        Self::with_selector(UniformSelector)
+    }
+}
+
+impl InMemoryLedger {
+    pub fn with_selector(selector: impl DecoySelector + 'static) -> Self {
+        Self {
+            outputs: Default::default(),
+            index: Default::default(),
+            spent: Default::default(),
+            selector: Arc::new(selector),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+}
+
+impl Ledger for InMemoryLedger {
+    fn log(&mut self, public_key: G1Affine, commitment: G1Affine) {
+        match self.index.get(&public_key.to_compressed()) {
+            Some(i) => self.outputs[*i].1 = commitment,
+            None => {
+                self.index
+                    .insert(public_key.to_compressed(), self.outputs.len());
+                self.outputs.push((public_key, commitment));
+            }
+        }
+    }
+
+    fn lookup(&self, public_key: &G1Affine) -> Option<G1Affine> {
+        self.index
+            .get(&public_key.to_compressed())
+            .map(|i| self.outputs[*i].1)
+    }
+
+    fn fetch_decoys(
+        &self,
+        n: usize,
+        exclude: &[G1Affine],
+        rng: &mut dyn RngCore,
+    ) -> Vec<DecoyInput> {
+        let exclude = exclude
+            .iter()
+            .filter_map(|pk| self.index.get(&pk.to_compressed()).copied())
+            .collect();
+
+        self.selector
+            .select(self.outputs.len(), n, &exclude, rng)
+            .into_iter()
+            .map(|i| DecoyInput {
+                public_key: self.outputs[i].0,
+                commitment: self.outputs[i].1,
+            })
+            .collect()
+    }
+
+    fn is_spent(&self, key_image: &G1Affine) -> bool {
+        self.spent.contains(&key_image.to_compressed())
+    }
+
+    fn mark_spent(&mut self, key_image: G1Affine) {
+        self.spent.insert(key_image.to_compressed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::{
+        group::{Curve, Group},
+        G1Projective,
+    };
+    use rand_core::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn test_in_memory_ledger() {
+        let mut ledger = InMemoryLedger::default();
+        let keys = Vec::from_iter((0..4).map(|_| G1Projective::random(OsRng).to_affine()));
+        let commitments = Vec::from_iter((0..4).map(|_| G1Projective::random(OsRng).to_affine()));
+        for (pk, c) in keys.iter().zip(commitments.iter()) {
+            ledger.log(*pk, *c);
+        }
+        assert_eq!(ledger.len(), 4);
+        assert_eq!(ledger.lookup(&keys[2]), Some(commitments[2]));
+        assert_eq!(ledger.lookup(&commitments[2]), None);
+
+        let decoys = ledger.fetch_decoys(3, &keys[..2], &mut OsRng);
+        assert_eq!(decoys.len(), 2);
+        assert!(decoys.iter().all(|d| !keys[..2].contains(&d.public_key)));
+
+        let mut gamma_ledger = InMemoryLedger::with_selector(crate::GammaSelector::default());
+        for (pk, c) in keys.iter().zip(commitments.iter()) {
+            gamma_ledger.log(*pk, *c);
+        }
+        let decoys = gamma_ledger.fetch_decoys(3, &keys[..1], &mut OsRng);
+        assert_eq!(decoys.len(), 3);
+        assert!(decoys.iter().all(|d| d.public_key != keys[0]));
+
+        assert!(!ledger.is_spent(&keys[0]));
+        ledger.mark_spent(keys[0]);
+        assert!(ledger.is_spent(&keys[0]));
+    }
+}
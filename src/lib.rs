@@ -1,19 +1,54 @@
+pub mod batch;
+pub mod builder;
+pub mod clsag;
+pub mod decoy;
+mod encoding;
 pub mod error;
+mod fixed_base;
+pub mod hash_cache;
+mod hash_rng;
+pub mod ledger;
 pub mod mlsag;
 pub mod ringct;
+#[cfg(feature = "serde")]
+mod serde_impl;
+pub mod spent;
+pub mod stealth;
+mod trace;
+pub mod wallet;
+#[cfg(feature = "zeroize")]
+mod zeroize_impl;
 
-use blstrs::{group::ff::Field, G1Projective, Scalar};
+use blstrs::{
+    group::{ff::Field, prime::PrimeCurveAffine, Curve},
+    G1Affine, G1Projective, Scalar,
+};
 
+pub use batch::{verify_batch, verify_batch_with_cache};
 pub use blstrs;
+pub use builder::TransactionBuilder;
+pub use clsag::ClsagSignature;
+pub use decoy::{DecoySelector, GammaSelector, RecentBiasedSelector, UniformSelector};
 pub use error::Error;
-pub use mlsag::{DecoyInput, MlsagMaterial, MlsagSignature, TrueInput};
-pub use ringct::{Output, RingCtMaterial};
+pub use hash_cache::HashToCurveCache;
+pub use ledger::{InMemoryLedger, Ledger};
+pub use mlsag::{DecoyInput, MlsagMaterial, MlsagSecrets, MlsagSignature, TrueInput};
+pub use ringct::{Output, RingCtMaterial, RingSignatureScheme, VerificationReport};
+pub use spent::{FileSpentKeyImages, InMemorySpentKeyImages, SpentKeyImages};
+pub use stealth::{
+    EncryptedAmount, SharedSecret, StealthAddress, StealthSecretKeys, TransactionKey,
+};
+pub use wallet::{OwnedOutput, Wallet};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Clone, Copy)]
+/// The opening of a commitment. The blinding is secret, so it is redacted from
+/// `Debug` output and, with the `zeroize` feature, wiped on drop.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RevealedCommitment {
     pub value: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::scalar"))]
     pub blinding: Scalar,
 }
 
@@ -46,18 +81,196 @@ impl RevealedCommitment {
     }
 }
 
+impl std::fmt::Debug for RevealedCommitment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RevealedCommitment")
+            .field("value", &self.value)
+            .field("blinding", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Implements `Drop` for a type holding secrets, wiping it with the `zeroize`
+/// feature. `Drop` is implemented either way, so enabling the feature does not
+/// change what code using the type may do, e.g. move fields out of it.
+macro_rules! zeroize_on_drop {
+    ($($ty:ty),*) => {
+        $(
+            impl Drop for $ty {
+                fn drop(&mut self) {
+                    #[cfg(feature = "zeroize")]
+                    zeroize::Zeroize::zeroize(self);
+                }
+            }
+        )*
+    };
+}
+
+zeroize_on_drop!(
+    RevealedCommitment,
+    TrueInput,
+    MlsagSecrets,
+    StealthSecretKeys,
+    TransactionKey,
+    SharedSecret
+);
+
 /// Hashes a point to another point on the G1 curve
 pub fn hash_to_curve(p: G1Projective) -> G1Projective {
     const DOMAIN: &[u8; 25] = b"blst-ringct-hash-to-curve";
     G1Projective::hash_to_curve(&p.to_compressed(), DOMAIN, &[])
 }
 
+/// Checks that `point` is not the identity and lies in the prime order
+/// subgroup of G1, failing with `identity_err` or `subgroup_err` respectively.
+///
+/// Points we decode ourselves are already subgroup checked, but the fields of
+/// our public types can be set to anything, so verification checks again.
+pub(crate) fn validate_point(
+    point: &G1Affine,
+    identity_err: Error,
+    subgroup_err: Error,
+) -> Result<()> {
+    if bool::from(point.is_identity()) {
+        Err(identity_err)
+    } else if !bool::from(point.is_on_curve() & point.is_torsion_free()) {
+        Err(subgroup_err)
+    } else {
+        Ok(())
+    }
+}
+
+/// Validates a key image, see `validate_point`
+pub(crate) fn validate_key_image(key_image: &G1Affine) -> Result<()> {
+    if !bool::from(key_image.is_on_curve()) {
+        return Err(Error::KeyImageNotOnCurve);
+    }
+    validate_point(
+        key_image,
+        Error::KeyImageIsIdentity,
+        Error::KeyImageNotInSubgroup,
+    )
+}
+
+/// Checks a ring signature is well formed, i.e. it has a non-empty ring and
+/// one response per ring member, and that its key images, ring public keys
+/// and pseudo commitment are valid, see `validate_point`.
+pub(crate) fn validate_ring_signature<'a>(
+    public_keys: impl ExactSizeIterator<Item = &'a G1Affine>,
+    responses: usize,
+    key_images: &[G1Affine],
+    pseudo_commitment: &G1Affine,
+) -> Result<()> {
+    // A signature decoded from an untrusted source may not be well formed
+    if public_keys.len() == 0 || responses != public_keys.len() {
+        return Err(Error::InvalidRingSignature);
+    }
+    for key_image in key_images {
+        validate_key_image(key_image)?;
+    }
+    validate_ring_points(public_keys, pseudo_commitment)
+}
+
+/// The hidden commitment C - C' of each ring member, from the public
+/// commitments C of the `ring_len` ring members and the pseudo commitment C'.
+pub(crate) fn hidden_commitments(
+    ring_len: usize,
+    public_commitments: &[G1Affine],
+    pseudo_commitment: &G1Affine,
+) -> Result<Vec<G1Affine>> {
+    if public_commitments.len() != ring_len {
+        return Err(Error::ExpectedAPublicCommitmentsForEachRingEntry);
+    }
+    let pseudo_commitment = G1Projective::from(pseudo_commitment);
+    let hidden = Vec::from_iter(public_commitments.iter().map(|c| c - pseudo_commitment));
+    let mut affine = vec![G1Affine::identity(); hidden.len()];
+    G1Projective::batch_normalize(&hidden, &mut affine);
+    Ok(affine)
+}
+
+/// Checks the hidden commitments of a ring of (public key, hidden commitment)
+/// pairs were computed as C - C', see `hidden_commitments`.
+pub(crate) fn check_hidden_commitments(
+    ring: &[(G1Affine, G1Affine)],
+    public_commitments: &[G1Affine],
+    pseudo_commitment: &G1Affine,
+) -> Result<()> {
+    let expected = hidden_commitments(ring.len(), public_commitments, pseudo_commitment)?;
+    for (i, ((_, hidden_commitment), expected)) in ring.iter().zip(expected).enumerate() {
+        if *hidden_commitment != expected {
+            return Err(Error::InvalidHiddenCommitmentInRing.at_ring_member(i));
+        }
+    }
+    Ok(())
+}
+
+/// Validates the public keys of a ring and its pseudo commitment, see `validate_point`
+pub(crate) fn validate_ring_points<'a>(
+    public_keys: impl IntoIterator<Item = &'a G1Affine>,
+    pseudo_commitment: &G1Affine,
+) -> Result<()> {
+    for (i, public_key) in public_keys.into_iter().enumerate() {
+        validate_point(
+            public_key,
+            Error::RingPublicKeyIsIdentity,
+            Error::RingPublicKeyNotInSubgroup,
+        )
+        .map_err(|e| e.at_ring_member(i))?;
+    }
+    validate_point(
+        pseudo_commitment,
+        Error::PseudoCommitmentIsIdentity,
+        Error::PseudoCommitmentNotInSubgroup,
+    )
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
 
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    /// The point with x = 4 is on the curve but not in the prime order subgroup
+    pub(crate) fn point_outside_subgroup() -> G1Affine {
+        let mut bytes = [0u8; 48];
+        bytes[0] = 0x80; // compressed flag
+        bytes[47] = 4;
+        let point = G1Affine::from_compressed_unchecked(&bytes).unwrap();
+        assert!(bool::from(point.is_on_curve()));
+        assert!(!bool::from(point.is_torsion_free()));
+        point
+    }
+
+    #[test]
+    fn test_revealed_commitment_debug_is_redacted() {
+        let revealed = RevealedCommitment::from_value(3, rand_core::OsRng);
+        let debug = format!("{:?}", revealed);
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains(&format!("{:?}", revealed.blinding)));
+    }
+
+    #[test]
+    fn test_validate_point() {
+        assert!(validate_key_image(&G1Affine::generator()).is_ok());
+        assert_eq!(
+            validate_key_image(&G1Affine::identity()),
+            Err(Error::KeyImageIsIdentity)
+        );
+        assert_eq!(
+            validate_key_image(&point_outside_subgroup()),
+            Err(Error::KeyImageNotInSubgroup)
+        );
+        assert_eq!(
+            validate_ring_points(&[point_outside_subgroup()], &G1Affine::generator()),
+            Err(Error::RingPublicKeyNotInSubgroup.at_ring_member(0))
+        );
+        assert_eq!(
+            validate_ring_points(&[G1Affine::generator()], &G1Affine::identity()),
+            Err(Error::PseudoCommitmentIsIdentity)
+        );
+    }
 }
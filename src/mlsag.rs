@@ -7,8 +7,18 @@ use bulletproofs::PedersenGens;
 use rand_core::RngCore;
 use tiny_keccak::{Hasher, Sha3};
 
-use crate::{Error, Result, RevealedCommitment};
+use crate::{
+    encoding::{Reader, G1_COMPRESSED_LEN, SCALAR_LEN},
+    fixed_base,
+    hash_cache::{hash_to_curve_with, HashToCurveCache},
+    hash_rng::HashRng,
+    Error, Ledger, Result, RevealedCommitment,
+};
+
+const MATERIAL_SEED_DOMAIN: &[u8] = b"blst-ringct-mlsag-material";
+const SIGNING_NONCE_DOMAIN: &[u8] = b"blst-ringct-signing-nonces";
 
+#[derive(Clone)]
 pub struct TrueInput {
     pub secret_key: Scalar,
     pub revealed_commitment: RevealedCommitment,
@@ -36,8 +46,11 @@ impl TrueInput {
 }
 
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DecoyInput {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::point"))]
     pub public_key: G1Affine,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::point"))]
     pub commitment: G1Affine,
 }
 
@@ -51,10 +64,22 @@ impl DecoyInput {
     }
 }
 
+/// The material for signing one input.
+///
+/// Its secrets are held by `true_input` and `secrets`, which wipe themselves
+/// on drop with the `zeroize` feature, so it can still be taken apart.
 pub struct MlsagMaterial {
     pub true_input: TrueInput,
     pub decoy_inputs: Vec<DecoyInput>,
+    pub secrets: MlsagSecrets,
+}
+
+/// The random choices made for signing an input. `pi_base` places the true
+/// input amongst the decoys, so it must stay as secret as the nonces.
+pub struct MlsagSecrets {
     pub pi_base: u32,
+    /// Entropy for the signing nonces, which are derived from it together
+    /// with what is being signed, see `MlsagMaterial::nonces`
     pub alpha: (Scalar, Scalar),
     pub r: Vec<(Scalar, Scalar)>,
 }
@@ -68,18 +93,48 @@ impl MlsagMaterial {
         let pi_base = rng.next_u32();
 
         let ring_len = decoy_inputs.len() + 1;
-        let alpha = (Scalar::random(&mut rng), Scalar::random(&mut rng));
-        let r: Vec<(Scalar, Scalar)> = (0..ring_len)
-            .map(|_| (Scalar::random(&mut rng), Scalar::random(&mut rng)))
-            .collect();
+        let (alpha, r) = random_nonces(ring_len, &mut rng);
 
         Self {
             true_input,
             decoy_inputs,
-            pi_base,
-            alpha,
-            r,
+            secrets: MlsagSecrets { pi_base, alpha, r },
+        }
+    }
+
+    /// Like `new`, but the placement of the true input and the nonce entropy
+    /// are derived from `seed` and the ring members.
+    pub fn from_seed(
+        true_input: TrueInput,
+        decoy_inputs: Vec<DecoyInput>,
+        seed: &[u8; 32],
+    ) -> Self {
+        let mut ring = true_input.public_key().to_compressed().to_vec();
+        for decoy in decoy_inputs.iter() {
+            ring.extend(decoy.public_key.to_compressed());
+            ring.extend(decoy.commitment.to_compressed());
+        }
+
+        let rng = HashRng::new(MATERIAL_SEED_DOMAIN, &[seed, &ring]);
+        Self::new(true_input, decoy_inputs, rng)
+    }
+
+    /// Like `new`, fetching `decoy_count` decoys for `true_input` from `ledger`.
+    pub fn from_ledger(
+        true_input: TrueInput,
+        ledger: &impl Ledger,
+        decoy_count: usize,
+        mut rng: impl RngCore,
+    ) -> Result<Self> {
+        let decoy_inputs = ledger.fetch_decoys(
+            decoy_count,
+            &[true_input.public_key().to_affine()],
+            &mut rng,
+        );
+        if decoy_inputs.len() < decoy_count {
+            return Err(Error::NotEnoughDecoys);
         }
+        Ok(Self::new(true_input, decoy_inputs, rng))
     }
 
     pub fn count_inputs(&self) -> usize {
@@ -89,7 +144,7 @@ impl MlsagMaterial {
     // Determines the index of the true input that will be randomly placed
     // amongst the decoys
     pub fn pi(&self) -> usize {
-        self.pi_base as usize % (self.decoy_inputs.len() + 1)
+        self.secrets.pi_base as usize % (self.decoy_inputs.len() + 1)
     }
 
     pub fn public_keys(&self) -> Vec<G1Affine> {
@@ -105,42 +160,124 @@ impl MlsagMaterial {
         cs
     }
 
+    /// The ring of (public key, hidden commitment) pairs to sign over.
+    pub(crate) fn ring(
+        &self,
+        pseudo_commitment: G1Projective,
+        pc_gens: &PedersenGens,
+    ) -> Vec<(G1Affine, G1Affine)> {
+        // commitment = r G + a H -- a is the amount, r is the blinding factor
+        // pseudo_commitment = v G + a H
+        // commitment - pseudo_commitment = (r G + a H) - (v G + a H)
+        //                                = (r - v) G + 0 H = (r - v) G
+        self.public_keys()
+            .into_iter()
+            .zip(self.commitments(pc_gens))
+            .map(|(pk, commitment)| (pk, (commitment - pseudo_commitment).to_affine()))
+            .collect()
+    }
+
+    /// Hp(P) of the true input's public key P and the key image x Hp(P)
+    pub(crate) fn key_image_with(
+        &self,
+        cache: Option<&HashToCurveCache>,
+    ) -> (G1Projective, G1Projective) {
+        let hashed_true_key = hash_to_curve_with(cache, &self.true_input.public_key().to_affine());
+        (
+            hashed_true_key,
+            hashed_true_key * self.true_input.secret_key,
+        )
+    }
+
+    /// The signing nonces (alpha, r) for signing `msg` with the given
+    /// pseudo-commitment, derived from `self.secrets.alpha`, `self.secrets.r`,
+    /// the secret key and both of those.
+    ///
+    /// Reusing nonces for a different message or pseudo-commitment would
+    /// reveal the secret key, so they are never used as stored. Signing the
+    /// same message twice gives the same signature.
+    pub(crate) fn nonces(
+        &self,
+        msg: &[u8],
+        revealed_pseudo_commitment: &RevealedCommitment,
+    ) -> ((Scalar, Scalar), Vec<(Scalar, Scalar)>) {
+        let entropy = Vec::from_iter(
+            [self.secrets.alpha]
+                .iter()
+                .chain(self.secrets.r.iter())
+                .flat_map(|(a, b)| [a.to_bytes_le(), b.to_bytes_le()])
+                .flatten(),
+        );
+        let secret_key = self.true_input.secret_key.to_bytes_le();
+        let pseudo_commitment = revealed_pseudo_commitment.to_bytes();
+        let rng = HashRng::new(
+            SIGNING_NONCE_DOMAIN,
+            &[&entropy, &secret_key, &pseudo_commitment, msg],
+        );
+        random_nonces(self.count_inputs(), rng)
+    }
+
     pub fn sign(
         &self,
         msg: &[u8],
         revealed_pseudo_commitment: &RevealedCommitment,
         pc_gens: &PedersenGens,
+    ) -> MlsagSignature {
+        self.sign_with_nonces(
+            msg,
+            revealed_pseudo_commitment,
+            pc_gens,
+            self.nonces(msg, revealed_pseudo_commitment),
+            self.key_image_with(None),
+            None,
+        )
+    }
+
+    /// Like `sign`, looking up the hashes of ring members in `cache`
+    pub fn sign_with_cache(
+        &self,
+        msg: &[u8],
+        revealed_pseudo_commitment: &RevealedCommitment,
+        pc_gens: &PedersenGens,
+        cache: &HashToCurveCache,
+    ) -> MlsagSignature {
+        self.sign_with_nonces(
+            msg,
+            revealed_pseudo_commitment,
+            pc_gens,
+            self.nonces(msg, revealed_pseudo_commitment),
+            self.key_image_with(Some(cache)),
+            Some(cache),
+        )
+    }
+
+    /// Signs using the given nonces in place of those from `nonces`, and the
+    /// hashed true key and key image from `key_image_with`
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(ring_size = self.count_inputs()))
+    )]
+    pub(crate) fn sign_with_nonces(
+        &self,
+        msg: &[u8],
+        revealed_pseudo_commitment: &RevealedCommitment,
+        pc_gens: &PedersenGens,
+        (alpha, mut r): ((Scalar, Scalar), Vec<(Scalar, Scalar)>),
+        (hashed_true_key, key_image): (G1Projective, G1Projective),
+        cache: Option<&HashToCurveCache>,
     ) -> MlsagSignature {
         #[allow(non_snake_case)]
         let G1 = G1Projective::generator(); // TAI: should we use pedersen.G instead?
 
-        let public_keys = self.public_keys();
-        let commitments = self.commitments(pc_gens);
-        let (pi, alpha, mut r) = (self.pi(), self.alpha, self.r.clone());
+        let pi = self.pi();
 
         let pseudo_commitment = revealed_pseudo_commitment.commit(pc_gens);
-
-        // commitment = r G + a H -- a is the amount, r is the blinding factor
-        // pseudo_commitment = v G + a H
-        // commitment - pseudo_commitment = (r G + a H) - (v G + a H)
-        //                                = (r - v) G + 0 H = (r - v) G
-
-        let ring: Vec<(G1Affine, G1Affine)> = public_keys
-            .into_iter()
-            .zip(commitments)
-            .map(|(pk, commitment)| (pk, (commitment - pseudo_commitment).to_affine()))
-            .collect();
-
-        let key_image = self.true_input.key_image();
+        let ring = self.ring(pseudo_commitment, pc_gens);
 
         let mut c: Vec<Scalar> = (0..ring.len()).map(|_| Scalar::zero()).collect();
 
-        c[(pi + 1) % ring.len()] = c_hash(
-            msg,
-            G1 * alpha.0,
-            G1 * alpha.1,
-            crate::hash_to_curve(ring[pi].0.into()) * alpha.0,
-        );
+        c[(pi + 1) % ring.len()] =
+            c_hash(msg, G1 * alpha.0, G1 * alpha.1, hashed_true_key * alpha.0);
 
         for offset in 1..ring.len() {
             let n = (pi + offset) % ring.len();
@@ -148,7 +285,7 @@ impl MlsagMaterial {
                 msg,
                 G1 * r[n].0 + ring[n].0 * c[n],
                 G1 * r[n].1 + ring[n].1 * c[n],
-                crate::hash_to_curve(ring[n].0.into()) * r[n].0 + key_image * c[n],
+                hash_to_curve_with(cache, &ring[n].0) * r[n].0 + key_image * c[n],
             );
         }
 
@@ -229,17 +366,27 @@ impl MlsagMaterial {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MlsagSignature {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::scalar"))]
     pub c0: Scalar,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::scalar_pairs"))]
     pub r: Vec<(Scalar, Scalar)>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::point"))]
     pub key_image: G1Affine,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::point_pairs"))]
     pub ring: Vec<(G1Affine, G1Affine)>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::point"))]
     pub pseudo_commitment: G1Affine,
 }
 
 impl MlsagSignature {
+    /// Serializes the signature as:
+    ///   ring length (u32 LE) | c0 | r pairs | key image | ring | pseudo-commitment
+    /// Scalars are 32 byte little-endian, points are 48 byte compressed G1.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut v: Vec<u8> = Default::default();
+        v.extend(&(self.ring.len() as u32).to_le_bytes());
         v.extend(&self.c0.to_bytes_le());
         for (x, y) in self.r.iter() {
             v.extend(x.to_bytes_le());
@@ -254,6 +401,38 @@ impl MlsagSignature {
         v
     }
 
+    /// Decodes a signature produced by `MlsagSignature::to_bytes`.
+    /// Every point must be a valid G1 point and every scalar must be canonical.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let sig = Self::read(&mut reader)?;
+        reader.finish()?;
+        Ok(sig)
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        // each ring member contributes two scalars and two points
+        let ring_len = reader.read_len(2 * SCALAR_LEN + 2 * G1_COMPRESSED_LEN)?;
+
+        let c0 = reader.read_scalar()?;
+        let r = (0..ring_len)
+            .map(|_| Ok((reader.read_scalar()?, reader.read_scalar()?)))
+            .collect::<Result<Vec<_>>>()?;
+        let key_image = reader.read_g1()?;
+        let ring = (0..ring_len)
+            .map(|_| Ok((reader.read_g1()?, reader.read_g1()?)))
+            .collect::<Result<Vec<_>>>()?;
+        let pseudo_commitment = reader.read_g1()?;
+
+        Ok(Self {
+            c0,
+            r,
+            key_image,
+            ring,
+            pseudo_commitment,
+        })
+    }
+
     pub fn pseudo_commitment(&self) -> G1Affine {
         self.pseudo_commitment
     }
@@ -263,42 +442,69 @@ impl MlsagSignature {
     }
 
     pub fn verify(&self, msg: &[u8], public_commitments: &[G1Affine]) -> Result<()> {
-        if self.ring.len() != public_commitments.len() {
-            return Err(Error::ExpectedAPublicCommitmentsForEachRingEntry);
-        }
-        // Check that hidden commitments in the ring where computed with: C - C'
-        for ((_, hidden_commitment), public_commitment) in self.ring.iter().zip(public_commitments)
-        {
-            if G1Projective::from(hidden_commitment)
-                != public_commitment - G1Projective::from(self.pseudo_commitment)
-            {
-                return Err(Error::InvalidHiddenCommitmentInRing);
-            }
-        }
+        self.verify_with(msg, public_commitments, None)
+    }
 
-        #[allow(non_snake_case)]
-        let G1 = G1Projective::generator();
+    /// Like `verify`, looking up the hashes of ring members in `cache`
+    pub fn verify_with_cache(
+        &self,
+        msg: &[u8],
+        public_commitments: &[G1Affine],
+        cache: &HashToCurveCache,
+    ) -> Result<()> {
+        self.verify_with(msg, public_commitments, Some(cache))
+    }
 
-        // Verify key image is in G
-        if !bool::from(self.key_image.is_on_curve()) {
-            // TODO: I don't think this is enough, we need to check that key_image is in the group as well
-            println!("Key images not on curve");
-            return Err(Error::KeyImageNotOnCurve);
-        }
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(ring_size = self.ring.len()))
+    )]
+    pub(crate) fn verify_with(
+        &self,
+        msg: &[u8],
+        public_commitments: &[G1Affine],
+        cache: Option<&HashToCurveCache>,
+    ) -> Result<()> {
+        self.validate_points()?;
+        crate::check_hidden_commitments(&self.ring, public_commitments, &self.pseudo_commitment)?;
+        self.verify_hash_chain(msg, cache)
+    }
+
+    /// Verifies the ring signature itself, without checking the hidden
+    /// commitments against the public commitments of the ring members.
+    pub(crate) fn verify_ring(&self, msg: &[u8], cache: Option<&HashToCurveCache>) -> Result<()> {
+        self.validate_points()?;
+        self.verify_hash_chain(msg, cache)
+    }
+
+    /// Checks the signature is well formed and all of its points are valid
+    /// group elements.
+    fn validate_points(&self) -> Result<()> {
+        crate::validate_ring_signature(
+            self.ring.iter().map(|(pk, _)| pk),
+            self.r.len(),
+            &[self.key_image],
+            &self.pseudo_commitment,
+        )
+    }
+
+    fn verify_hash_chain(&self, msg: &[u8], cache: Option<&HashToCurveCache>) -> Result<()> {
+        let key_image = G1Projective::from(self.key_image);
 
         let mut cprime = Vec::from_iter((0..self.ring.len()).map(|_| Scalar::zero()));
         cprime[0] = self.c0;
 
+        // The G products use the precomputed generator table. Two point
+        // multi-scalar multiplications measured slower than separate products.
         for (n, keys) in self.ring.iter().enumerate() {
             cprime[(n + 1) % self.ring.len()] = c_hash(
                 msg,
-                G1 * self.r[n].0 + keys.0 * cprime[n],
-                G1 * self.r[n].1 + keys.1 * cprime[n],
-                crate::hash_to_curve(keys.0.into()) * self.r[n].0 + self.key_image * cprime[n],
+                fixed_base::mul_generator(&self.r[n].0) + keys.0 * cprime[n],
+                fixed_base::mul_generator(&self.r[n].1) + keys.1 * cprime[n],
+                hash_to_curve_with(cache, &keys.0) * self.r[n].0 + key_image * cprime[n],
             );
         }
 
-        println!("c': {:#?}", cprime);
         if self.c0 != cprime[0] {
             Err(Error::InvalidRingSignature)
         } else {
@@ -307,6 +513,18 @@ impl MlsagSignature {
     }
 }
 
+/// Draws the signing nonces (alpha, r) for a ring of `ring_len` members
+pub(crate) fn random_nonces(
+    ring_len: usize,
+    mut rng: impl RngCore,
+) -> ((Scalar, Scalar), Vec<(Scalar, Scalar)>) {
+    let alpha = (Scalar::random(&mut rng), Scalar::random(&mut rng));
+    let r = (0..ring_len)
+        .map(|_| (Scalar::random(&mut rng), Scalar::random(&mut rng)))
+        .collect();
+    (alpha, r)
+}
+
 fn c_hash(msg: &[u8], l1: G1Projective, l2: G1Projective, r1: G1Projective) -> Scalar {
     hash_to_scalar(&[
         msg,
@@ -317,7 +535,7 @@ fn c_hash(msg: &[u8], l1: G1Projective, l2: G1Projective, r1: G1Projective) -> S
 }
 
 /// Hashes given material to a Scalar, repeated hashing is used if a hash can not be interpreted as a Scalar
-fn hash_to_scalar(material: &[&[u8]]) -> Scalar {
+pub(crate) fn hash_to_scalar(material: &[&[u8]]) -> Scalar {
     let mut sha3 = Sha3::v256();
     for chunk in material {
         sha3.update(chunk);
@@ -335,3 +553,183 @@ fn hash_to_scalar(material: &[&[u8]]) -> Scalar {
         sha3.finalize(&mut hash);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use blstrs::group::{ff::Field, prime::PrimeCurveAffine, Curve, Group};
+    use rand_core::OsRng;
+
+    use super::*;
+
+    fn test_signature(ring_size: usize) -> MlsagSignature {
+        let mut rng = OsRng;
+        let pc_gens = PedersenGens::default();
+
+        let true_input = TrueInput {
+            secret_key: Scalar::random(&mut rng),
+            revealed_commitment: RevealedCommitment::from_value(5, &mut rng),
+        };
+        let decoy_inputs = (1..ring_size)
+            .map(|_| DecoyInput {
+                public_key: G1Projective::random(&mut rng).to_affine(),
+                commitment: G1Projective::random(&mut rng).to_affine(),
+            })
+            .collect();
+
+        let revealed_pseudo_commitment = true_input.random_pseudo_commitment(&mut rng);
+        let material = MlsagMaterial::new(true_input, decoy_inputs, &mut rng);
+        material.sign(b"test message", &revealed_pseudo_commitment, &pc_gens)
+    }
+
+    #[test]
+    fn test_mlsag_rejects_invalid_points() {
+        let sig = test_signature(4);
+        let msg = b"test message";
+        assert!(sig.verify_ring(msg, None).is_ok());
+
+        let mut bad = sig.clone();
+        bad.key_image = G1Affine::identity();
+        assert_eq!(bad.verify_ring(msg, None), Err(Error::KeyImageIsIdentity));
+
+        let mut bad = sig.clone();
+        bad.key_image = crate::tests::point_outside_subgroup();
+        assert_eq!(
+            bad.verify_ring(msg, None),
+            Err(Error::KeyImageNotInSubgroup)
+        );
+
+        let mut bad = sig.clone();
+        bad.ring[1].0 = G1Affine::identity();
+        assert_eq!(
+            bad.verify_ring(msg, None),
+            Err(Error::RingPublicKeyIsIdentity.at_ring_member(1))
+        );
+
+        let mut bad = sig;
+        bad.ring[2].0 = crate::tests::point_outside_subgroup();
+        assert_eq!(
+            bad.verify_ring(msg, None),
+            Err(Error::RingPublicKeyNotInSubgroup.at_ring_member(2))
+        );
+    }
+
+    #[test]
+    fn test_mlsag_nonces_are_never_reused() {
+        let mut rng = OsRng;
+        let true_input = TrueInput {
+            secret_key: Scalar::random(&mut rng),
+            revealed_commitment: RevealedCommitment::from_value(5, &mut rng),
+        };
+        let decoy_inputs = vec![DecoyInput {
+            public_key: G1Projective::random(&mut rng).to_affine(),
+            commitment: G1Projective::random(&mut rng).to_affine(),
+        }];
+        let material = MlsagMaterial::from_seed(true_input, decoy_inputs, &[1u8; 32]);
+        let pseudo_commitment = material.true_input.random_pseudo_commitment(&mut rng);
+        let other_pseudo_commitment = material.true_input.random_pseudo_commitment(&mut rng);
+
+        let nonces = material.nonces(b"message", &pseudo_commitment);
+        assert_eq!(nonces, material.nonces(b"message", &pseudo_commitment));
+        assert_ne!(nonces.0, material.secrets.alpha);
+        assert_ne!(
+            nonces.0,
+            material.nonces(b"other message", &pseudo_commitment).0
+        );
+        assert_ne!(
+            nonces.0,
+            material.nonces(b"message", &other_pseudo_commitment).0
+        );
+    }
+
+    #[test]
+    fn test_mlsag_with_cache() {
+        let mut rng = OsRng;
+        let pc_gens = PedersenGens::default();
+        let true_input = TrueInput {
+            secret_key: Scalar::random(&mut rng),
+            revealed_commitment: RevealedCommitment::from_value(5, &mut rng),
+        };
+        let decoy_inputs = (1..4)
+            .map(|_| DecoyInput {
+                public_key: G1Projective::random(&mut rng).to_affine(),
+                commitment: G1Projective::random(&mut rng).to_affine(),
+            })
+            .collect();
+        let revealed_pseudo_commitment = true_input.random_pseudo_commitment(&mut rng);
+        let material = MlsagMaterial::new(true_input, decoy_inputs, &mut rng);
+        let public_commitments = material.commitments(&pc_gens);
+
+        let msg = b"test message";
+        let cache = HashToCurveCache::new(8);
+        let sig = material.sign_with_cache(msg, &revealed_pseudo_commitment, &pc_gens, &cache);
+        assert_eq!(cache.len(), 4);
+        assert_eq!(
+            sig.to_bytes(),
+            material
+                .sign(msg, &revealed_pseudo_commitment, &pc_gens)
+                .to_bytes()
+        );
+        assert!(sig
+            .verify_with_cache(msg, &public_commitments, &cache)
+            .is_ok());
+        assert_eq!(
+            sig.verify_with_cache(b"another message", &public_commitments, &cache),
+            sig.verify(b"another message", &public_commitments)
+        );
+    }
+
+    #[test]
+    fn test_mlsag_bytes_round_trip() {
+        let sig = test_signature(4);
+        let decoded = MlsagSignature::from_bytes(&sig.to_bytes()).expect("Failed to decode");
+
+        assert_eq!(decoded.c0, sig.c0);
+        assert_eq!(decoded.r, sig.r);
+        assert_eq!(decoded.key_image, sig.key_image);
+        assert_eq!(decoded.ring, sig.ring);
+        assert_eq!(decoded.pseudo_commitment, sig.pseudo_commitment);
+        assert_eq!(decoded.to_bytes(), sig.to_bytes());
+    }
+
+    #[test]
+    fn test_mlsag_from_bytes_rejects_malformed_input() {
+        let bytes = test_signature(3).to_bytes();
+
+        assert_eq!(
+            MlsagSignature::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            Error::TruncatedInput
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            MlsagSignature::from_bytes(&trailing).unwrap_err(),
+            Error::TrailingBytes
+        );
+
+        // c0 is encoded right after the ring length, an all 0xff scalar is not canonical
+        let mut bad_scalar = bytes.clone();
+        bad_scalar[4..36].copy_from_slice(&[0xff; 32]);
+        assert_eq!(
+            MlsagSignature::from_bytes(&bad_scalar).unwrap_err(),
+            Error::InvalidScalarEncoding
+        );
+
+        // the pseudo-commitment is the last point in the encoding
+        let mut bad_point = bytes.clone();
+        let len = bad_point.len();
+        bad_point[len - 48..].copy_from_slice(&[0xff; 48]);
+        assert_eq!(
+            MlsagSignature::from_bytes(&bad_point).unwrap_err(),
+            Error::InvalidG1PointEncoding
+        );
+
+        // a ring length that the remaining input can not possibly hold
+        let mut bad_len = bytes;
+        bad_len[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            MlsagSignature::from_bytes(&bad_len).unwrap_err(),
+            Error::TruncatedInput
+        );
+    }
+}
@@ -1,18 +1,60 @@
-use blstrs::{group::Curve, group::GroupEncoding, G1Affine, G1Projective, Scalar};
+use std::{collections::BTreeSet, sync::OnceLock};
+
+use blstrs::{
+    group::{ff::Field, prime::PrimeCurveAffine, Curve, GroupEncoding},
+    G1Affine, G1Projective, Scalar,
+};
 use bulletproofs::{BulletproofGens, PedersenGens, RangeProof};
 use merlin::Transcript;
-use rand_core::RngCore;
+use rand_core::{CryptoRng, RngCore};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use tiny_keccak::{Hasher, Sha3};
 
-use crate::{Error, MlsagMaterial, MlsagSignature, Result, RevealedCommitment};
+use crate::{
+    clsag::clsag_nonces,
+    encoding::{optional_g1_bytes, Reader, G1_COMPRESSED_LEN},
+    hash_cache::HashToCurveCache,
+    hash_rng::HashRng,
+    mlsag::random_nonces,
+    stealth::ENCRYPTED_AMOUNT_LEN,
+    trace, ClsagSignature, EncryptedAmount, Error, Ledger, MlsagMaterial, MlsagSignature, Result,
+    RevealedCommitment, SharedSecret, SpentKeyImages,
+};
 pub(crate) const RANGE_PROOF_BITS: usize = 64; // note: Range Proof max-bits is 64. allowed are: 8, 16, 32, 64 (only)
                                                //       This limits our amount field to 64 bits also.
-pub(crate) const RANGE_PROOF_PARTIES: usize = 1; // The maximum number of parties that can produce an aggregated proof
 pub(crate) const MERLIN_TRANSCRIPT_LABEL: &[u8] = b"BLST_RINGCT";
+pub(crate) const WIRE_MAGIC: &[u8; 4] = b"RCTX"; // prefix of every encoded RingCtTransaction
+pub(crate) const WIRE_VERSION: u16 = 1; // bump when the encoding changes after a release
+
+/// The fewest ring members, the true input included, that `RingCtMaterial::sign` accepts
+pub const MIN_RING_SIZE: usize = 2;
+
+/// The most outputs a transaction may have. This bounds the range proof
+/// generators a verifier needs, a power of two so no padding goes beyond it.
+pub const MAX_OUTPUTS: usize = 16;
+
+// domain separators for the deterministic RNGs used by seed driven signing
+const BLINDING_SEED_DOMAIN: &[u8] = b"blst-ringct-blindings";
+const NONCE_SEED_DOMAIN: &[u8] = b"blst-ringct-nonces";
+const RANGE_PROOF_RNG_DOMAIN: &[u8] = b"blst-ringct-range-proof";
 
+// smallest possible encodings, used to sanity check length prefixes while decoding
+const MIN_MLSAG_LEN: usize = 4 + 32 + 2 * G1_COMPRESSED_LEN; // ring len, c0, key image, pseudo-commitment
+const MIN_CLSAG_LEN: usize = 4 + 32 + 3 * G1_COMPRESSED_LEN; // ring len, c0, key images, pseudo-commitment
+const MIN_OUTPUT_PROOF_LEN: usize = 2 * G1_COMPRESSED_LEN + 1; // public key, commitment, encrypted amount flag
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Output {
+    /// The key the output is paid to, see `TransactionKey::output` for
+    /// one-time keys derived from a stealth address.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::point"))]
     pub public_key: G1Affine,
     pub amount: u64,
+    /// When set the amount and blinding are encrypted for the recipient
+    /// under this secret, see `EncryptedAmount`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub shared_secret: Option<SharedSecret>,
 }
 
 impl Output {
@@ -30,67 +72,292 @@ impl Output {
     }
 }
 
+/// The ring signature used to sign every input of a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RingSignatureScheme {
+    #[default]
+    Mlsag,
+    /// Smaller signatures and faster verification, see `crate::clsag`
+    Clsag,
+}
+
 #[derive(Default)]
 pub struct RingCtMaterial {
     pub inputs: Vec<MlsagMaterial>,
     pub outputs: Vec<Output>,
+    /// Public fee paid by the transaction, the inputs must cover the outputs plus this fee
+    pub fee: u64,
+    /// R = r G of the `TransactionKey` used to derive stealth outputs
+    pub tx_public_key: Option<G1Affine>,
+    pub scheme: RingSignatureScheme,
 }
 
 impl RingCtMaterial {
+    /// Signs the transaction, drawing every blinding factor from `rng`.
     pub fn sign(
         &self,
-        rng: impl RngCore + Copy,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(RingCtTransaction, Vec<RevealedCommitment>)> {
+        self.sign_with(rng, None)
+    }
+
+    /// Like `sign`, looking up the hashes of ring members in `cache`
+    pub fn sign_with_cache(
+        &self,
+        rng: &mut (impl RngCore + CryptoRng),
+        cache: &HashToCurveCache,
+    ) -> Result<(RingCtTransaction, Vec<RevealedCommitment>)> {
+        self.sign_with(rng, Some(cache))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(inputs = self.inputs.len(), outputs = self.outputs.len(), scheme = ?self.scheme)
+        )
+    )]
+    fn sign_with(
+        &self,
+        rng: &mut (impl RngCore + CryptoRng),
+        cache: Option<&HashToCurveCache>,
+    ) -> Result<(RingCtTransaction, Vec<RevealedCommitment>)> {
+        self.validate()?;
+
+        // Both sets of blindings must come from the same stream, a copy of the
+        // RNG would repeat the pseudo-commitment blindings in the outputs.
+        let revealed_pseudo_commitments = self.revealed_pseudo_commitments(&mut *rng);
+        let revealed_output_commitments =
+            self.revealed_output_commitments(&revealed_pseudo_commitments, &mut *rng);
+
+        self.sign_with_commitments(
+            revealed_pseudo_commitments,
+            revealed_output_commitments,
+            None,
+            cache,
+        )
+    }
+
+    /// Signs the transaction deterministically.
+    ///
+    /// The pseudo-commitment and output blindings are derived from `seed` and
+    /// the inputs and outputs of this material, the signing nonces from `seed`,
+    /// the message being signed and each input's secrets. Together with
+    /// `MlsagMaterial::from_seed` the same seed and material always produce
+    /// the same transaction.
+    pub fn sign_with_seed(
+        &self,
+        seed: &[u8; 32],
+    ) -> Result<(RingCtTransaction, Vec<RevealedCommitment>)> {
+        self.sign_with_seed_with(seed, None)
+    }
+
+    /// Like `sign_with_seed`, looking up the hashes of ring members in `cache`
+    pub fn sign_with_seed_and_cache(
+        &self,
+        seed: &[u8; 32],
+        cache: &HashToCurveCache,
+    ) -> Result<(RingCtTransaction, Vec<RevealedCommitment>)> {
+        self.sign_with_seed_with(seed, Some(cache))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(inputs = self.inputs.len(), outputs = self.outputs.len(), scheme = ?self.scheme)
+        )
+    )]
+    fn sign_with_seed_with(
+        &self,
+        seed: &[u8; 32],
+        cache: Option<&HashToCurveCache>,
+    ) -> Result<(RingCtTransaction, Vec<RevealedCommitment>)> {
+        self.validate()?;
+
+        let mut rng = self.blinding_rng(seed);
+        let revealed_pseudo_commitments = self.revealed_pseudo_commitments(&mut rng);
+        let revealed_output_commitments =
+            self.revealed_output_commitments(&revealed_pseudo_commitments, &mut rng);
+
+        self.sign_with_commitments(
+            revealed_pseudo_commitments,
+            revealed_output_commitments,
+            Some(seed),
+            cache,
+        )
+    }
+
+    /// Recomputes the revealed output commitments of a transaction created by
+    /// `sign_with_seed`, so a signer does not need to store them.
+    pub fn revealed_output_commitments_from_seed(
+        &self,
+        seed: &[u8; 32],
+    ) -> Result<Vec<RevealedCommitment>> {
+        self.validate()?;
+
+        let mut rng = self.blinding_rng(seed);
+        let revealed_pseudo_commitments = self.revealed_pseudo_commitments(&mut rng);
+        Ok(self.revealed_output_commitments(&revealed_pseudo_commitments, &mut rng))
+    }
+
+    /// Checks the material describes a transaction that would verify, so
+    /// mistakes are caught before signing rather than by verifiers.
+    pub fn validate(&self) -> Result<()> {
+        if self.inputs.is_empty() {
+            return Err(Error::NoInputs);
+        }
+        if self.outputs.is_empty() {
+            return Err(Error::NoOutputs);
+        }
+        if self.outputs.len() > MAX_OUTPUTS {
+            return Err(Error::TooManyOutputs);
+        }
+        for m in self.inputs.iter() {
+            if m.count_inputs() < MIN_RING_SIZE {
+                return Err(Error::RingTooSmall);
+            }
+            if m.secrets.r.len() != m.count_inputs() {
+                return Err(Error::MismatchedMaterial);
+            }
+        }
+
+        let input_sum = checked_sum(
+            self.inputs
+                .iter()
+                .map(|m| m.true_input.revealed_commitment.value),
+        )?;
+        let output_sum = checked_sum(self.outputs.iter().map(Output::amount).chain([self.fee]))?;
+        if input_sum != output_sum {
+            return Err(Error::InputsAndOutputsDoNotBalance);
+        }
+        Ok(())
+    }
+
+    /// Signs with the given blindings. When `nonce_seed` is set the signing
+    /// nonces are derived from it, see `seeded_nonces`, otherwise from each
+    /// `MlsagMaterial`, see `MlsagMaterial::nonces`.
+    fn sign_with_commitments(
+        &self,
+        revealed_pseudo_commitments: Vec<RevealedCommitment>,
+        revealed_output_commitments: Vec<RevealedCommitment>,
+        nonce_seed: Option<&[u8; 32]>,
+        cache: Option<&HashToCurveCache>,
     ) -> Result<(RingCtTransaction, Vec<RevealedCommitment>)> {
         // We need to gather a bunch of things for our message to sign.
         //   All public keys in all rings
         //   All key-images,
         //   All PseudoCommitments
         //   All output commitments
-        //   All output range proofs
+        //   The aggregated range proof over all outputs
         //
         //   notes:
         //     1. the real pk is randomly mixed with decoys by MlsagMaterial
-        //     2. output commitments and range_proofs are bundled together
-        //        in OutputProofs
-        //     3. all these must be generated in proper order. It would be nice
-        //        to make RingCtMaterial deterministic by instantiating with a seed.
-        let revealed_pseudo_commitments = self.revealed_pseudo_commitments(rng);
+        //     2. a single range proof covers every output commitment
+        //     3. all these must be generated in proper order, see sign_with_seed()
+        //        for a deterministic ordering.
         let pseudo_commitments = self.pseudo_commitments(&revealed_pseudo_commitments);
-        let revealed_output_commitments =
-            self.revealed_output_commitments(&revealed_pseudo_commitments, rng);
-        let output_proofs = self.output_range_proofs(&revealed_output_commitments)?;
+        let (range_proof, output_proofs) = trace::phase("range_proof", || {
+            self.output_range_proof(&revealed_output_commitments)
+        })?;
+
+        // Each input's key image is computed once, for the message and its signature
+        let key_images = Vec::from_iter(self.inputs.iter().map(|m| m.key_image_with(cache)));
 
         // Generate message to sign.
         // note: must match message generated by RingCtTransaction::verify()
         let msg = gen_message_for_signing(
             &self.public_keys(),
-            &self.key_images(),
+            &Vec::from_iter(
+                key_images
+                    .iter()
+                    .map(|(_, key_image)| key_image.to_affine()),
+            ),
             &pseudo_commitments,
             &output_proofs,
+            &range_proof,
+            self.fee,
+            &self.tx_public_key,
         );
 
-        // We create a ring signature for each input
-        let mlsags: Vec<MlsagSignature> = self
+        let nonces = |i: usize, m: &MlsagMaterial, r: &RevealedCommitment| match nonce_seed {
+            Some(seed) => seeded_nonces(seed, &msg, i, m, r),
+            None => m.nonces(&msg, r),
+        };
+
+        // We create a ring signature for each input, in input order either way
+        #[cfg(not(feature = "parallel"))]
+        let signing_material = self
             .inputs
             .iter()
             .zip(revealed_pseudo_commitments.iter())
-            .map(|(m, r)| m.sign(&msg, r, &Self::pc_gens()))
-            .collect();
+            .zip(key_images.iter())
+            .enumerate();
+        #[cfg(feature = "parallel")]
+        let signing_material = self
+            .inputs
+            .par_iter()
+            .zip(revealed_pseudo_commitments.par_iter())
+            .zip(key_images.par_iter())
+            .enumerate();
+        let (mlsags, clsags) = trace::phase("ring_signatures", || match self.scheme {
+            RingSignatureScheme::Mlsag => (
+                signing_material
+                    .map(|(i, ((m, r), key_image))| {
+                        m.sign_with_nonces(
+                            &msg,
+                            r,
+                            &Self::pc_gens(),
+                            nonces(i, m, r),
+                            *key_image,
+                            cache,
+                        )
+                    })
+                    .collect(),
+                vec![],
+            ),
+            RingSignatureScheme::Clsag => (
+                vec![],
+                signing_material
+                    .map(|(i, ((m, r), key_image))| {
+                        ClsagSignature::sign_with_nonces(
+                            m,
+                            &msg,
+                            r,
+                            &Self::pc_gens(),
+                            clsag_nonces(nonces(i, m, r)),
+                            *key_image,
+                            cache,
+                        )
+                    })
+                    .collect(),
+            ),
+        });
 
         Ok((
             RingCtTransaction {
                 mlsags,
+                clsags,
                 outputs: output_proofs,
+                range_proof,
+                fee: self.fee,
+                tx_public_key: self.tx_public_key,
             },
             revealed_output_commitments,
         ))
     }
 
-    fn bp_gens() -> BulletproofGens {
-        BulletproofGens::new(RANGE_PROOF_BITS, RANGE_PROOF_PARTIES)
+    /// Generators for an aggregated range proof over up to `MAX_OUTPUTS`
+    /// outputs. They are built on first use and shared by every proof, a proof
+    /// over fewer outputs uses the first of them.
+    fn bp_gens() -> &'static BulletproofGens {
+        static BP_GENS: OnceLock<BulletproofGens> = OnceLock::new();
+        BP_GENS.get_or_init(|| BulletproofGens::new(RANGE_PROOF_BITS, MAX_OUTPUTS))
     }
 
-    fn pc_gens() -> PedersenGens {
+    pub(crate) fn pc_gens() -> PedersenGens {
         Default::default()
     }
 
@@ -105,6 +372,26 @@ impl RingCtMaterial {
             .collect()
     }
 
+    /// The RNG for the blinding factors of `sign_with_seed`, bound to the seed
+    /// and everything about the transaction that does not depend on them.
+    fn blinding_rng(&self, seed: &[u8; 32]) -> HashRng {
+        let mut material: Vec<u8> = vec![self.scheme as u8];
+        for m in self.inputs.iter() {
+            material.extend(&(m.count_inputs() as u32).to_le_bytes());
+            for (pk, commitment) in m.public_keys().iter().zip(m.commitments(&Self::pc_gens())) {
+                material.extend(pk.to_compressed());
+                material.extend(commitment.to_compressed());
+            }
+        }
+        for out in self.outputs.iter() {
+            material.extend(out.public_key.to_compressed());
+            material.extend(&out.amount.to_le_bytes());
+        }
+        material.extend(&self.fee.to_le_bytes());
+        material.extend(optional_g1_bytes(&self.tx_public_key));
+        HashRng::new(BLINDING_SEED_DOMAIN, &[seed, &material])
+    }
+
     fn revealed_pseudo_commitments(&self, mut rng: impl RngCore) -> Vec<RevealedCommitment> {
         self.inputs
             .iter()
@@ -146,44 +433,108 @@ impl RingCtMaterial {
 
         let output_blinding_correction = input_sum - output_sum;
 
+        // `validate` ensures there is at least one output
         if let Some(last_output) = self.outputs.last() {
             revealed_output_commitments.push(RevealedCommitment {
                 value: last_output.amount,
                 blinding: output_blinding_correction,
             });
-        } else {
-            panic!("Expected at least one output")
         }
         revealed_output_commitments
     }
 
-    fn output_range_proofs(
+    /// Creates one aggregated range proof covering every output commitment.
+    fn output_range_proof(
         &self,
         revealed_output_commitments: &[RevealedCommitment],
-    ) -> Result<Vec<OutputProof>> {
+    ) -> Result<(RangeProof, Vec<OutputProof>)> {
         let mut prover_ts = Transcript::new(MERLIN_TRANSCRIPT_LABEL);
 
-        revealed_output_commitments
+        // Aggregated proofs need a power of two number of parties, we pad with
+        // commitments to zero using a zero blinding factor, i.e. the identity,
+        // which the verifier can reconstruct on its own.
+        let parties = range_proof_parties(revealed_output_commitments.len());
+        let mut values: Vec<u64> = revealed_output_commitments
+            .iter()
+            .map(RevealedCommitment::value)
+            .collect();
+        let mut blindings: Vec<Scalar> = revealed_output_commitments
+            .iter()
+            .map(RevealedCommitment::blinding)
+            .collect();
+        values.resize(parties, 0);
+        blindings.resize(parties, Scalar::zero());
+
+        // The proof randomness is derived from the secret blindings, so a
+        // deterministic signer also produces a deterministic proof.
+        let witness = Vec::from_iter(revealed_output_commitments.iter().map(|r| r.to_bytes()));
+        let witness = Vec::from_iter(witness.iter().map(Vec::as_slice));
+        let mut rng = HashRng::new(RANGE_PROOF_RNG_DOMAIN, &witness);
+
+        let (range_proof, commitments) = RangeProof::prove_multiple_with_rng(
+            Self::bp_gens(),
+            &Self::pc_gens(),
+            &mut prover_ts,
+            &values,
+            &blindings,
+            RANGE_PROOF_BITS,
+            &mut rng,
+        )?;
+
+        let output_proofs = self
+            .outputs
             .iter()
-            .map(|revealed_commitment| {
-                let (range_proof, commitment) = RangeProof::prove_single(
-                    &Self::bp_gens(),
-                    &Self::pc_gens(),
-                    &mut prover_ts,
-                    revealed_commitment.value,
-                    &revealed_commitment.blinding,
-                    RANGE_PROOF_BITS,
-                )?;
-
-                Ok(OutputProof {
-                    range_proof,
+            .zip(revealed_output_commitments)
+            .zip(commitments)
+            .enumerate()
+            .map(
+                |(i, ((output, revealed_commitment), commitment))| OutputProof {
+                    public_key: output.public_key,
                     commitment,
-                })
-            })
-            .collect::<Result<Vec<_>>>()
+                    encrypted_amount: output
+                        .shared_secret
+                        .as_ref()
+                        .map(|s| EncryptedAmount::encrypt(revealed_commitment, s, i)),
+                },
+            )
+            .collect();
+
+        Ok((range_proof, output_proofs))
     }
 }
 
+/// The signing nonces of input `i` for `sign_with_seed`. Like
+/// `MlsagMaterial::nonces` they are bound to the secret key and the revealed
+/// pseudo-commitment, so knowing the seed is not enough to recover them.
+fn seeded_nonces(
+    seed: &[u8; 32],
+    msg: &[u8],
+    i: usize,
+    m: &MlsagMaterial,
+    revealed_pseudo_commitment: &RevealedCommitment,
+) -> ((Scalar, Scalar), Vec<(Scalar, Scalar)>) {
+    let input_index = (i as u64).to_le_bytes();
+    let secret_key = m.true_input.secret_key.to_bytes_le();
+    let pseudo_commitment = revealed_pseudo_commitment.to_bytes();
+    let rng = HashRng::new(
+        NONCE_SEED_DOMAIN,
+        &[seed, msg, &input_index, &secret_key, &pseudo_commitment],
+    );
+    random_nonces(m.count_inputs(), rng)
+}
+
+/// The number of parties in the aggregated range proof over `num_outputs` outputs
+fn range_proof_parties(num_outputs: usize) -> usize {
+    num_outputs.next_power_of_two()
+}
+
+pub(crate) fn checked_sum(amounts: impl IntoIterator<Item = u64>) -> Result<u64> {
+    amounts
+        .into_iter()
+        .try_fold(0u64, |sum, amount| sum.checked_add(amount))
+        .ok_or(Error::AmountOverflow)
+}
+
 // note: used by both RingCtMaterial::sign and RingCtTransaction::verify()
 //       which must match.
 fn gen_message_for_signing(
@@ -191,6 +542,9 @@ fn gen_message_for_signing(
     key_images: &[G1Affine],
     pseudo_commitments: &[G1Affine],
     output_proofs: &[OutputProof],
+    range_proof: &RangeProof,
+    fee: u64,
+    tx_public_key: &Option<G1Affine>,
 ) -> Vec<u8> {
     // Generate message to sign.
     let mut msg: Vec<u8> = Default::default();
@@ -206,50 +560,210 @@ fn gen_message_for_signing(
     for o in output_proofs.iter() {
         msg.extend(o.to_bytes());
     }
+    msg.extend(range_proof.to_bytes());
+    msg.extend(&fee.to_le_bytes());
+    msg.extend(optional_g1_bytes(tx_public_key));
     msg
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OutputProof {
-    range_proof: RangeProof,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::point"))]
+    public_key: G1Affine,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::point"))]
     commitment: G1Affine,
+    encrypted_amount: Option<EncryptedAmount>,
 }
 
 impl OutputProof {
+    /// Serializes the proof as:
+    ///   public key | commitment
+    ///   encrypted amount flag (u8, 0 or 1) | encrypted amount when the flag is 1
+    /// The range proof for the commitment is part of the transaction.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut v: Vec<u8> = Default::default();
-        v.extend(&self.range_proof.to_bytes());
+        v.extend(self.public_key.to_bytes().as_ref());
         v.extend(self.commitment.to_bytes().as_ref());
+        match &self.encrypted_amount {
+            Some(encrypted_amount) => {
+                v.push(1);
+                v.extend(encrypted_amount.to_bytes());
+            }
+            None => v.push(0),
+        }
         v
     }
 
-    pub fn range_proof(&self) -> &RangeProof {
-        &self.range_proof
+    /// Decodes an output proof produced by `OutputProof::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let proof = Self::read(&mut reader)?;
+        reader.finish()?;
+        Ok(proof)
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        let public_key = reader.read_g1()?;
+        let commitment = reader.read_g1()?;
+        let encrypted_amount = match reader.read_array::<1>()?[0] {
+            0 => None,
+            1 => Some(EncryptedAmount::from_bytes(
+                reader.read_array::<ENCRYPTED_AMOUNT_LEN>()?,
+            )),
+            flag => return Err(Error::InvalidOptionFlag(flag)),
+        };
+        Ok(Self {
+            public_key,
+            commitment,
+            encrypted_amount,
+        })
+    }
+
+    /// The key this output is paid to
+    pub fn public_key(&self) -> G1Affine {
+        self.public_key
     }
 
     pub fn commitment(&self) -> G1Affine {
         self.commitment
     }
+
+    pub fn encrypted_amount(&self) -> Option<&EncryptedAmount> {
+        self.encrypted_amount.as_ref()
+    }
+
+    /// Decrypts the amount sent to the recipient of output `index` and checks
+    /// that it opens the output commitment.
+    pub fn decrypt_amount(
+        &self,
+        shared_secret: &SharedSecret,
+        index: usize,
+    ) -> Result<RevealedCommitment> {
+        self.encrypted_amount
+            .as_ref()
+            .ok_or(Error::MissingEncryptedAmount)?
+            .open(
+                shared_secret,
+                index,
+                &self.commitment,
+                &RingCtMaterial::pc_gens(),
+            )
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RingCtTransaction {
     pub mlsags: Vec<MlsagSignature>,
+    pub clsags: Vec<ClsagSignature>,
     pub outputs: Vec<OutputProof>,
+    /// A single aggregated range proof covering every output commitment
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::range_proof"))]
+    pub range_proof: RangeProof,
+    /// Public fee, the pseudo-commitments sum to the output commitments plus `fee * H`
+    pub fee: u64,
+    /// R = r G, lets recipients find the outputs paid to their stealth address
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::optional_point"))]
+    pub tx_public_key: Option<G1Affine>,
+}
+
+/// Every failure found while verifying a transaction, in the order `verify`
+/// checks for them, see `RingCtTransaction::verification_report`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerificationReport {
+    pub failures: Vec<Error>,
+}
+
+impl VerificationReport {
+    pub fn is_valid(&self) -> bool {
+        self.failures.is_empty()
+    }
 }
 
 impl RingCtTransaction {
+    /// Serializes the transaction as:
+    ///   magic | version (u16 LE)
+    ///   fee (u64 LE)
+    ///   tx public key flag (u8, 0 or 1) | tx public key when the flag is 1
+    ///   mlsag count (u32 LE) | mlsags
+    ///   clsag count (u32 LE) | clsags
+    ///   output count (u32 LE) | output proofs
+    ///   range proof length (u32 LE) | range proof
+    ///
+    /// Only `WIRE_VERSION` is decoded, other versions are rejected. A future
+    /// encoding bumps the version and keeps decoding the versions before it.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut v: Vec<u8> = Default::default();
+        v.extend(WIRE_MAGIC);
+        v.extend(&WIRE_VERSION.to_le_bytes());
+        v.extend(&self.fee.to_le_bytes());
+        v.extend(optional_g1_bytes(&self.tx_public_key));
+        v.extend(&(self.mlsags.len() as u32).to_le_bytes());
         for m in self.mlsags.iter() {
             v.extend(&m.to_bytes());
         }
+        v.extend(&(self.clsags.len() as u32).to_le_bytes());
+        for c in self.clsags.iter() {
+            v.extend(&c.to_bytes());
+        }
+        v.extend(&(self.outputs.len() as u32).to_le_bytes());
         for o in self.outputs.iter() {
             v.extend(&o.to_bytes());
         }
+        let range_proof = self.range_proof.to_bytes();
+        v.extend(&(range_proof.len() as u32).to_le_bytes());
+        v.extend(&range_proof);
         v
     }
 
+    /// Decodes a transaction produced by `RingCtTransaction::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+
+        if reader.read_bytes(WIRE_MAGIC.len())? != WIRE_MAGIC {
+            return Err(Error::InvalidWireMagic);
+        }
+        let version = u16::from_le_bytes(reader.read_array()?);
+        if version != WIRE_VERSION {
+            return Err(Error::UnsupportedWireVersion(version));
+        }
+        let fee = u64::from_le_bytes(reader.read_array()?);
+        let tx_public_key = reader.read_optional_g1()?;
+
+        let mlsags_len = reader.read_len(MIN_MLSAG_LEN)?;
+        let mlsags = (0..mlsags_len)
+            .map(|_| MlsagSignature::read(&mut reader))
+            .collect::<Result<Vec<_>>>()?;
+
+        let clsags_len = reader.read_len(MIN_CLSAG_LEN)?;
+        let clsags = (0..clsags_len)
+            .map(|_| ClsagSignature::read(&mut reader))
+            .collect::<Result<Vec<_>>>()?;
+
+        let outputs_len = reader.read_len(MIN_OUTPUT_PROOF_LEN)?;
+        if outputs_len > MAX_OUTPUTS {
+            return Err(Error::TooManyOutputs);
+        }
+        let outputs = (0..outputs_len)
+            .map(|_| OutputProof::read(&mut reader))
+            .collect::<Result<Vec<_>>>()?;
+
+        let range_proof_len = reader.read_len(1)?;
+        let range_proof = RangeProof::from_bytes(reader.read_bytes(range_proof_len)?)?;
+
+        reader.finish()?;
+
+        Ok(Self {
+            mlsags,
+            clsags,
+            outputs,
+            range_proof,
+            fee,
+            tx_public_key,
+        })
+    }
+
     pub fn hash(&self) -> [u8; 32] {
         let mut sha3 = Sha3::v256();
 
@@ -260,49 +774,213 @@ impl RingCtTransaction {
         hash
     }
 
+    /// The public keys of each input's ring, MLSAG inputs first, then CLSAG inputs.
+    pub fn ring_public_keys(&self) -> Vec<Vec<G1Affine>> {
+        self.mlsags
+            .iter()
+            .map(MlsagSignature::public_keys)
+            .chain(self.clsags.iter().map(ClsagSignature::public_keys))
+            .collect()
+    }
+
+    /// The key image of each input, MLSAG inputs first, then CLSAG inputs.
+    pub fn key_images(&self) -> Vec<G1Affine> {
+        self.mlsags
+            .iter()
+            .map(|m| m.key_image)
+            .chain(self.clsags.iter().map(|c| c.key_image))
+            .collect()
+    }
+
+    /// The pseudo-commitment of each input, MLSAG inputs first, then CLSAG inputs.
+    pub fn pseudo_commitments(&self) -> Vec<G1Affine> {
+        self.mlsags
+            .iter()
+            .map(MlsagSignature::pseudo_commitment)
+            .chain(self.clsags.iter().map(ClsagSignature::pseudo_commitment))
+            .collect()
+    }
+
     // note: must match message generated by RingCtMaterial::sign()
     pub fn gen_message(&self) -> Vec<u8> {
         // All public keys in all rings
-        let public_keys: Vec<G1Affine> = self.mlsags.iter().flat_map(|m| m.public_keys()).collect();
-
-        // All key-images (of true inputs),
-        let key_images: Vec<G1Affine> = self.mlsags.iter().map(|m| m.key_image).collect();
-
-        // All PseudoCommitments.
-        let pseudo_commitments: Vec<G1Affine> =
-            self.mlsags.iter().map(|m| m.pseudo_commitment()).collect();
+        let public_keys: Vec<G1Affine> = self.ring_public_keys().into_iter().flatten().collect();
 
         gen_message_for_signing(
             &public_keys,
-            &key_images,
-            &pseudo_commitments,
+            &self.key_images(),
+            &self.pseudo_commitments(),
             &self.outputs,
+            &self.range_proof,
+            self.fee,
+            &self.tx_public_key,
         )
     }
 
-    pub fn verify(&self, public_commitments_per_ring: &[Vec<G1Affine>]) -> Result<()> {
-        let msg = self.gen_message();
-        for (mlsag, public_commitments) in self.mlsags.iter().zip(public_commitments_per_ring) {
-            mlsag.verify(&msg, public_commitments)?
+    /// Like `verify`, resolving the public commitments of every ring member
+    /// through `ledger`. This does not check whether the key images are spent.
+    pub fn verify_with_ledger(&self, ledger: &impl Ledger) -> Result<()> {
+        self.verify(&ledger.public_commitments(self)?)
+    }
+
+    /// Verifies the transaction and rejects it if any of its key images is
+    /// already in `spent`, or appears twice in the transaction. Only once
+    /// the transaction is fully valid are its key images recorded, all at once.
+    pub fn verify_and_spend(
+        &self,
+        public_commitments_per_ring: &[Vec<G1Affine>],
+        spent: &mut impl SpentKeyImages,
+    ) -> Result<()> {
+        self.verify(public_commitments_per_ring)?;
+
+        let key_images = self.key_images();
+        let mut seen = BTreeSet::new();
+        for key_image in key_images.iter() {
+            if !seen.insert(key_image.to_compressed()) || spent.contains(key_image)? {
+                return Err(Error::KeyImageAlreadySpent);
+            }
         }
 
-        let mut prover_ts = Transcript::new(MERLIN_TRANSCRIPT_LABEL);
+        spent.insert_all(&key_images)
+    }
 
-        for output in self.outputs.iter() {
-            // Verification requires a transcript with identical initial state:
-            output.range_proof.verify_single(
-                &RingCtMaterial::bp_gens(),
-                &RingCtMaterial::pc_gens(),
-                &mut prover_ts,
-                &output.commitment,
-                RANGE_PROOF_BITS,
-            )?;
+    /// `public_commitments_per_ring` holds the public commitments for each
+    /// input's ring, in the order given by `RingCtTransaction::ring_public_keys`.
+    ///
+    /// Returns the first failure found, errors about a single input or output
+    /// carry its index. See `verification_report` to collect every failure.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(inputs = self.mlsags.len() + self.clsags.len(), outputs = self.outputs.len())
+        )
+    )]
+    pub fn verify(&self, public_commitments_per_ring: &[Vec<G1Affine>]) -> Result<()> {
+        self.checks(public_commitments_per_ring, None).collect()
+    }
+
+    /// Like `verify`, looking up the hashes of ring members in `cache`
+    pub fn verify_with_cache(
+        &self,
+        public_commitments_per_ring: &[Vec<G1Affine>],
+        cache: &HashToCurveCache,
+    ) -> Result<()> {
+        self.checks(public_commitments_per_ring, Some(cache))
+            .collect()
+    }
+
+    /// Runs every check of `verify` without stopping at the first failure.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(inputs = self.mlsags.len() + self.clsags.len(), outputs = self.outputs.len())
+        )
+    )]
+    pub fn verification_report(
+        &self,
+        public_commitments_per_ring: &[Vec<G1Affine>],
+    ) -> VerificationReport {
+        VerificationReport {
+            failures: self
+                .checks(public_commitments_per_ring, None)
+                .filter_map(Result::err)
+                .collect(),
         }
+    }
+
+    /// Every check made by `verify`, run lazily and in order
+    pub(crate) fn checks<'a>(
+        &'a self,
+        public_commitments_per_ring: &'a [Vec<G1Affine>],
+        cache: Option<&'a HashToCurveCache>,
+    ) -> impl Iterator<Item = Result<()>> + 'a {
+        // Checked first: it bounds the work of the checks that follow
+        let structure =
+            std::iter::once_with(|| trace::check("structure", || self.validate_structure()));
+
+        let rings = self.mlsags.len() + self.clsags.len();
+        let ring_count = std::iter::once_with(move || {
+            trace::check("ring_count", || {
+                if public_commitments_per_ring.len() == rings {
+                    Ok(())
+                } else {
+                    Err(Error::RingCountMismatch {
+                        expected: rings,
+                        found: public_commitments_per_ring.len(),
+                    })
+                }
+            })
+        });
 
+        let outputs = (0..self.outputs.len())
+            .map(|i| trace::check("output_commitment", || self.validate_output_commitment(i)));
+
+        let msg = self.gen_message();
+        let verify_input = move |(i, public_commitments): (usize, &Vec<G1Affine>)| {
+            trace::check("ring_signature", || {
+                match self.mlsags.get(i) {
+                    Some(mlsag) => mlsag.verify_with(&msg, public_commitments, cache),
+                    None => self.clsags[i - self.mlsags.len()].verify_with(
+                        &msg,
+                        public_commitments,
+                        cache,
+                    ),
+                }
+                .map_err(|e| e.at_input(i))
+            })
+        };
+        let verify_range_proof = || trace::check("range_proof", || self.verify_range_proof());
+
+        #[cfg(not(feature = "parallel"))]
+        let (inputs, range_proof) = (
+            public_commitments_per_ring
+                .iter()
+                .take(rings)
+                .enumerate()
+                .map(verify_input),
+            std::iter::once_with(verify_range_proof),
+        );
+
+        // The ring signatures and the range proof are checked together once
+        // the checks before them have been reached, the results are reported
+        // in the same order as the serial checks.
+        #[cfg(feature = "parallel")]
+        let (inputs, range_proof) = (
+            std::iter::once_with(move || {
+                rayon::join(
+                    || {
+                        public_commitments_per_ring
+                            .par_iter()
+                            .take(rings)
+                            .enumerate()
+                            .map(verify_input)
+                            .collect::<Vec<_>>()
+                    },
+                    verify_range_proof,
+                )
+            })
+            .flat_map(|(inputs, range_proof)| inputs.into_iter().chain([range_proof])),
+            std::iter::empty(),
+        );
+
+        structure
+            .chain(ring_count)
+            .chain(outputs)
+            .chain(inputs)
+            .chain(range_proof)
+            .chain(std::iter::once_with(|| {
+                trace::check("balance", || self.verify_balance())
+            }))
+    }
+
+    fn verify_balance(&self) -> Result<()> {
         let input_sum: G1Projective = self
-            .mlsags
-            .iter()
-            .map(MlsagSignature::pseudo_commitment)
+            .pseudo_commitments()
+            .into_iter()
             .map(G1Projective::from)
             .sum();
         let output_sum: G1Projective = self
@@ -311,107 +989,762 @@ impl RingCtTransaction {
             .map(OutputProof::commitment)
             .map(G1Projective::from)
             .sum();
+        // The fee is public, so it is committed to with a zero blinding factor
+        let fee_commitment =
+            RingCtMaterial::pc_gens().commit(Scalar::from(self.fee), Scalar::zero());
 
-        if input_sum != output_sum {
+        if input_sum != output_sum + fee_commitment {
             Err(Error::InputPseudoCommitmentsDoNotSumToOutputCommitments)
         } else {
             Ok(())
         }
     }
+
+    /// Checks the transaction has a shape `RingCtMaterial::validate` accepts.
+    pub(crate) fn validate_structure(&self) -> Result<()> {
+        if self.mlsags.is_empty() && self.clsags.is_empty() {
+            return Err(Error::NoInputs);
+        }
+        if self.outputs.is_empty() {
+            return Err(Error::NoOutputs);
+        }
+        if self.outputs.len() > MAX_OUTPUTS {
+            return Err(Error::TooManyOutputs);
+        }
+        let mut ring_lens = (self.mlsags.iter().map(|m| m.ring.len()))
+            .chain(self.clsags.iter().map(|c| c.ring.len()));
+        if ring_lens.any(|len| len < MIN_RING_SIZE) {
+            return Err(Error::RingTooSmall);
+        }
+        Ok(())
+    }
+
+    /// Checks every output commitment is a valid group element.
+    /// The identity is only valid as range proof padding, never as a real output.
+    pub(crate) fn validate_output_commitments(&self) -> Result<()> {
+        (0..self.outputs.len()).try_for_each(|i| self.validate_output_commitment(i))
+    }
+
+    fn validate_output_commitment(&self, index: usize) -> Result<()> {
+        crate::validate_point(
+            &self.outputs[index].commitment,
+            Error::OutputCommitmentIsIdentity,
+            Error::OutputCommitmentNotInSubgroup,
+        )
+        .map_err(|e| e.at_output(index))
+    }
+
+    pub(crate) fn verify_range_proof(&self) -> Result<()> {
+        // Verification requires a transcript with identical initial state:
+        let mut prover_ts = Transcript::new(MERLIN_TRANSCRIPT_LABEL);
+
+        // Rebuild the padding the prover added to reach a power of two
+        let mut commitments: Vec<G1Affine> =
+            self.outputs.iter().map(OutputProof::commitment).collect();
+        commitments.resize(
+            range_proof_parties(self.outputs.len()),
+            G1Affine::identity(),
+        );
+
+        self.range_proof.verify_multiple(
+            RingCtMaterial::bp_gens(),
+            &RingCtMaterial::pc_gens(),
+            &mut prover_ts,
+            &commitments,
+            RANGE_PROOF_BITS,
+        )?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
-mod tests {
-    use std::collections::{BTreeMap, BTreeSet};
-
+pub(crate) mod tests {
     use blstrs::group::{ff::Field, Curve, Group};
     use rand_core::OsRng;
 
-    use crate::{DecoyInput, MlsagMaterial, TrueInput};
+    use crate::{InMemoryLedger, InMemorySpentKeyImages, MlsagMaterial, TrueInput};
 
     use super::*;
 
-    #[derive(Default)]
-    struct TestLedger {
-        commitments: BTreeMap<[u8; 48], G1Affine>, // Compressed public keys -> Commitments
-    }
+    pub(crate) fn test_material(
+        ledger: &mut InMemoryLedger,
+        scheme: RingSignatureScheme,
+        mut rng: impl RngCore,
+    ) -> RingCtMaterial {
+        let pc_gens = PedersenGens::default();
 
-    impl TestLedger {
-        fn log(&mut self, public_key: impl Into<G1Affine>, commitment: impl Into<G1Affine>) {
-            self.commitments
-                .insert(public_key.into().to_compressed(), commitment.into());
-        }
+        let true_input = TrueInput {
+            secret_key: Scalar::random(&mut rng),
+            revealed_commitment: RevealedCommitment {
+                value: 3,
+                blinding: 5.into(),
+            },
+        };
 
-        fn lookup(&self, public_key: impl Into<G1Affine>) -> Option<G1Affine> {
-            self.commitments
-                .get(&public_key.into().to_compressed())
-                .copied()
+        ledger.log(
+            true_input.public_key().to_affine(),
+            true_input.revealed_commitment.commit(&pc_gens).to_affine(),
+        );
+        for _ in 0..2 {
+            ledger.log(
+                G1Projective::random(&mut rng).to_affine(),
+                G1Projective::random(&mut rng).to_affine(),
+            );
         }
 
-        fn fetch_decoys(&self, n: usize, exclude: &[G1Projective]) -> Vec<DecoyInput> {
-            let exclude_set = BTreeSet::from_iter(exclude.iter().map(G1Projective::to_compressed));
+        let input =
+            MlsagMaterial::from_ledger(true_input, ledger, 2, &mut rng).expect("Not enough decoys");
 
-            self.commitments
-                .iter()
-                .filter(|(pk, _)| !exclude_set.contains(*pk))
-                .map(|(pk, c)| DecoyInput {
-                    public_key: G1Affine::from_compressed(pk).unwrap(),
-                    commitment: *c,
-                })
-                .take(n)
-                .collect()
+        RingCtMaterial {
+            inputs: vec![input],
+            outputs: vec![Output {
+                public_key: G1Projective::random(&mut rng).to_affine(),
+                amount: 3,
+                shared_secret: None,
+            }],
+            fee: 0,
+            tx_public_key: None,
+            scheme,
         }
     }
 
     #[test]
     fn test_ringct_sign() {
-        let mut rng = OsRng::default();
-        let pc_gens = PedersenGens::default();
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+
+        let (signed_tx, _revealed_output_commitments) =
+            ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+
+        let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
+
+        assert!(signed_tx.verify(&public_commitments).is_ok());
+    }
+
+    #[test]
+    fn test_ringct_verify_with_ledger() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+
+        let (signed_tx, _revealed_output_commitments) =
+            ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        assert!(signed_tx.verify_with_ledger(&ledger).is_ok());
+
+        // Every ring member must be known to the ledger
+        assert_eq!(
+            signed_tx.verify_with_ledger(&InMemoryLedger::default()),
+            Err(Error::UnknownRingMember)
+        );
 
         let true_input = TrueInput {
             secret_key: Scalar::random(&mut rng),
-            revealed_commitment: RevealedCommitment {
-                value: 3,
-                blinding: 5.into(),
-            },
+            revealed_commitment: RevealedCommitment::from_value(1, &mut rng),
         };
+        assert!(matches!(
+            MlsagMaterial::from_ledger(true_input, &ledger, ledger.len() + 1, &mut rng),
+            Err(Error::NotEnoughDecoys)
+        ));
+    }
 
-        let mut ledger = TestLedger::default();
-        ledger.log(
-            true_input.public_key(),
-            true_input.revealed_commitment.commit(&pc_gens),
+    #[test]
+    fn test_ringct_verify_and_spend() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+        let (signed_tx, _) = ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
+
+        // An invalid transaction records nothing
+        let mut spent = InMemorySpentKeyImages::default();
+        let mut tampered = signed_tx.clone();
+        tampered.fee += 1;
+        assert!(tampered
+            .verify_and_spend(&public_commitments, &mut spent)
+            .is_err());
+        assert!(!spent.contains(&signed_tx.key_images()[0]).unwrap());
+
+        assert!(signed_tx
+            .verify_and_spend(&public_commitments, &mut spent)
+            .is_ok());
+        assert_eq!(
+            signed_tx.verify_and_spend(&public_commitments, &mut spent),
+            Err(Error::KeyImageAlreadySpent)
         );
-        ledger.log(
-            G1Projective::random(&mut rng),
-            G1Projective::random(&mut rng),
+
+        // The ledger can act as the registry too
+        assert!(signed_tx
+            .verify_and_spend(&public_commitments, &mut ledger)
+            .is_ok());
+        assert!(ledger.is_spent(&signed_tx.key_images()[0]));
+    }
+
+    #[test]
+    fn test_ringct_sign_with_fee() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let mut ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+        ring_ct.outputs[0].amount = 2;
+        ring_ct.fee = 1;
+
+        let (signed_tx, _revealed_output_commitments) =
+            ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        assert_eq!(signed_tx.fee, 1);
+
+        let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
+        assert!(signed_tx.verify(&public_commitments).is_ok());
+        assert!(crate::verify_batch(&[(&signed_tx, &public_commitments)]).is_ok());
+
+        let decoded =
+            RingCtTransaction::from_bytes(&signed_tx.to_bytes()).expect("Failed to decode");
+        assert_eq!(decoded.fee, 1);
+        assert!(decoded.verify(&public_commitments).is_ok());
+
+        // The fee is signed over
+        let mut bad_tx = signed_tx;
+        bad_tx.fee = 0;
+        assert_eq!(
+            bad_tx.verify(&public_commitments),
+            Err(Error::InvalidRingSignature.at_input(0))
         );
-        ledger.log(
-            G1Projective::random(&mut rng),
-            G1Projective::random(&mut rng),
+
+        // and must close the balance, which signing checks up front
+        ring_ct.fee = 0;
+        assert!(matches!(
+            ring_ct.sign(&mut rng),
+            Err(Error::InputsAndOutputsDoNotBalance)
+        ));
+
+        // so skip the check to see that verifiers catch it too
+        let revealed_pseudo_commitments = ring_ct.revealed_pseudo_commitments(&mut rng);
+        let revealed_output_commitments =
+            ring_ct.revealed_output_commitments(&revealed_pseudo_commitments, &mut rng);
+        let (unbalanced_tx, _) = ring_ct
+            .sign_with_commitments(
+                revealed_pseudo_commitments,
+                revealed_output_commitments,
+                None,
+                None,
+            )
+            .expect("Failed to sign transaction");
+        assert_eq!(
+            unbalanced_tx.verify(&public_commitments),
+            Err(Error::InputPseudoCommitmentsDoNotSumToOutputCommitments)
         );
+    }
 
-        let decoy_inputs = ledger.fetch_decoys(2, &[true_input.public_key()]);
+    #[test]
+    fn test_ringct_sign_rejects_invalid_material() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let check = |ring_ct: &RingCtMaterial, err: Error| {
+            assert_eq!(ring_ct.validate(), Err(err.clone()));
+            assert_eq!(ring_ct.sign(&mut OsRng).err(), Some(err.clone()));
+            assert_eq!(ring_ct.sign_with_seed(&[1u8; 32]).err(), Some(err));
+        };
+
+        let mut ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+        ring_ct.outputs[0].amount = 4;
+        check(&ring_ct, Error::InputsAndOutputsDoNotBalance);
+
+        ring_ct.outputs[0].amount = 3;
+        ring_ct.fee = u64::MAX;
+        check(&ring_ct, Error::AmountOverflow);
 
-        let ring_ct = RingCtMaterial {
-            inputs: vec![MlsagMaterial::new(true_input, decoy_inputs, &mut rng)],
+        let mut ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+        ring_ct.inputs[0].secrets.r.pop();
+        check(&ring_ct, Error::MismatchedMaterial);
+
+        ring_ct.inputs[0].decoy_inputs.clear();
+        check(&ring_ct, Error::RingTooSmall);
+
+        ring_ct.outputs = (0..=MAX_OUTPUTS)
+            .map(|_| Output {
+                public_key: G1Projective::random(&mut rng).to_affine(),
+                amount: 0,
+                shared_secret: None,
+            })
+            .collect();
+        check(&ring_ct, Error::TooManyOutputs);
+
+        ring_ct.outputs.clear();
+        check(&ring_ct, Error::NoOutputs);
+
+        ring_ct.inputs.clear();
+        check(&ring_ct, Error::NoInputs);
+    }
+
+    #[test]
+    fn test_ringct_stealth_output() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let pc_gens = PedersenGens::default();
+
+        let recipient = crate::StealthSecretKeys::random(&mut rng);
+        let tx_key = crate::TransactionKey::random(&mut rng);
+
+        let mut ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+        ring_ct.outputs = vec![tx_key.output(&recipient.address(), 0, 3)];
+        ring_ct.tx_public_key = Some(tx_key.public_key());
+
+        let (signed_tx, _revealed_output_commitments) =
+            ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
+        assert!(signed_tx.verify(&public_commitments).is_ok());
+
+        let decoded =
+            RingCtTransaction::from_bytes(&signed_tx.to_bytes()).expect("Failed to decode");
+        assert_eq!(decoded.tx_public_key, Some(tx_key.public_key()));
+
+        // The recipient learns the amount from the transaction alone
+        let tx_public_key = decoded.tx_public_key.unwrap();
+        let output = &decoded.outputs[0];
+        let shared_secret = recipient.shared_secret(&tx_public_key);
+        let revealed_commitment = output
+            .decrypt_amount(&shared_secret, 0)
+            .expect("Failed to decrypt amount");
+        assert_eq!(revealed_commitment.value, 3);
+
+        // and can spend the output with the one-time key
+        ledger.log(output.public_key(), output.commitment());
+        let true_input = recipient
+            .true_input(&tx_public_key, 0, &output.public_key(), revealed_commitment)
+            .expect("Output should be ours");
+        assert_eq!(
+            true_input.revealed_commitment.commit(&pc_gens).to_affine(),
+            output.commitment()
+        );
+
+        let spend = RingCtMaterial {
+            inputs: vec![MlsagMaterial::from_ledger(true_input, &ledger, 2, &mut rng).unwrap()],
             outputs: vec![Output {
                 public_key: G1Projective::random(&mut rng).to_affine(),
                 amount: 3,
+                shared_secret: None,
             }],
+            ..Default::default()
         };
+        let (spend_tx, _) = spend.sign(&mut rng).expect("Failed to sign transaction");
+        assert!(spend_tx.verify_with_ledger(&ledger).is_ok());
+
+        // Outputs without a shared secret carry no encrypted amount
+        assert_eq!(
+            spend_tx.outputs[0]
+                .decrypt_amount(&shared_secret, 0)
+                .unwrap_err(),
+            Error::MissingEncryptedAmount
+        );
+    }
+
+    #[test]
+    fn test_ringct_sign_clsag() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let ring_ct = test_material(&mut ledger, RingSignatureScheme::Clsag, &mut rng);
 
         let (signed_tx, _revealed_output_commitments) =
-            ring_ct.sign(rng).expect("Failed to sign transaction");
-
-        let public_commitments = Vec::from_iter(signed_tx.mlsags.iter().map(|mlsag| {
-            Vec::from_iter(
-                mlsag
-                    .public_keys()
-                    .into_iter()
-                    .map(|pk| ledger.lookup(pk).unwrap()),
-            )
-        }));
+            ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        assert!(signed_tx.mlsags.is_empty());
+        assert_eq!(signed_tx.clsags.len(), 1);
+
+        let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
+        assert!(signed_tx.verify(&public_commitments).is_ok());
+
+        let decoded =
+            RingCtTransaction::from_bytes(&signed_tx.to_bytes()).expect("Failed to decode");
+        assert!(decoded.verify(&public_commitments).is_ok());
+    }
+
+    #[test]
+    fn test_ringct_sign_blindings_are_distinct() {
+        let mut ledger = InMemoryLedger::default();
+        let mut ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, OsRng);
+        ring_ct.outputs = (0..3)
+            .map(|_| Output {
+                public_key: G1Projective::random(OsRng).to_affine(),
+                amount: 1,
+                shared_secret: None,
+            })
+            .collect();
+
+        // Replay the stream sign() consumes to recover the pseudo-commitment blindings
+        let mut rng = HashRng::new(b"test", &[]);
+        let (signed_tx, revealed_output_commitments) =
+            ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        let mut replay = HashRng::new(b"test", &[]);
+        let revealed_pseudo_commitments = ring_ct.revealed_pseudo_commitments(&mut replay);
+        assert_eq!(
+            signed_tx.pseudo_commitments(),
+            ring_ct.pseudo_commitments(&revealed_pseudo_commitments)
+        );
+
+        let blindings = Vec::from_iter(
+            revealed_pseudo_commitments
+                .iter()
+                .chain(revealed_output_commitments.iter())
+                .map(|r| r.blinding.to_bytes_le()),
+        );
+        assert_eq!(blindings.len(), 4);
+        assert_eq!(BTreeSet::from_iter(blindings.iter()).len(), blindings.len());
+    }
+
+    #[test]
+    fn test_ringct_sign_many_inputs() {
+        let mut ledger = InMemoryLedger::default();
+        let mut ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, OsRng);
+        for _ in 1..8 {
+            let mut other = test_material(&mut ledger, RingSignatureScheme::Mlsag, OsRng);
+            ring_ct.inputs.push(other.inputs.remove(0));
+        }
+        ring_ct.outputs[0].amount = 3 * 8;
+
+        let mut rng = HashRng::new(b"test", &[]);
+        let (signed_tx, _) = ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
+        assert!(signed_tx.verify(&public_commitments).is_ok());
+
+        // Each ring signature is the one its input signs on its own, in input order
+        let mut replay = HashRng::new(b"test", &[]);
+        let revealed_pseudo_commitments = ring_ct.revealed_pseudo_commitments(&mut replay);
+        let msg = signed_tx.gen_message();
+        for (i, (m, r)) in ring_ct
+            .inputs
+            .iter()
+            .zip(revealed_pseudo_commitments.iter())
+            .enumerate()
+        {
+            assert_eq!(
+                signed_tx.mlsags[i].to_bytes(),
+                m.sign(&msg, r, &RingCtMaterial::pc_gens()).to_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn test_ringct_sign_with_seed() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let seed = [7u8; 32];
+
+        for scheme in [RingSignatureScheme::Mlsag, RingSignatureScheme::Clsag] {
+            let mut ring_ct = test_material(&mut ledger, scheme, &mut rng);
+            let MlsagMaterial {
+                true_input,
+                decoy_inputs,
+                ..
+            } = ring_ct.inputs.remove(0);
+            ring_ct
+                .inputs
+                .push(MlsagMaterial::from_seed(true_input, decoy_inputs, &seed));
+
+            let (signed_tx, revealed_output_commitments) = ring_ct
+                .sign_with_seed(&seed)
+                .expect("Failed to sign transaction");
+            let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
+            assert!(signed_tx.verify(&public_commitments).is_ok());
+
+            // The same seed gives the same transaction
+            let (resigned_tx, _) = ring_ct.sign_with_seed(&seed).expect("Failed to sign");
+            assert_eq!(resigned_tx.to_bytes(), signed_tx.to_bytes());
+
+            // whether or not ring members are hashed through a cache
+            let cache = HashToCurveCache::new(64);
+            let (cached_tx, _) = ring_ct
+                .sign_with_seed_and_cache(&seed, &cache)
+                .expect("Failed to sign");
+            assert_eq!(cached_tx.to_bytes(), signed_tx.to_bytes());
+            assert!(!cache.is_empty());
+
+            // and the revealed output commitments can be recovered from it
+            let recovered = ring_ct
+                .revealed_output_commitments_from_seed(&seed)
+                .unwrap();
+            assert_eq!(
+                Vec::from_iter(recovered.iter().map(RevealedCommitment::to_bytes)),
+                Vec::from_iter(
+                    revealed_output_commitments
+                        .iter()
+                        .map(RevealedCommitment::to_bytes)
+                ),
+            );
+
+            let (other_tx, _) = ring_ct.sign_with_seed(&[8u8; 32]).expect("Failed to sign");
+            assert_ne!(other_tx.to_bytes(), signed_tx.to_bytes());
+        }
+    }
+
+    #[test]
+    fn test_ringct_seeded_nonces_bind_the_secret_key() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let seed = [7u8; 32];
+        let ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+        let material = &ring_ct.inputs[0];
+        let pseudo_commitment = material.true_input.random_pseudo_commitment(&mut rng);
 
+        let other_key = MlsagMaterial::from_seed(
+            TrueInput {
+                secret_key: Scalar::random(&mut rng),
+                revealed_commitment: material.true_input.revealed_commitment.clone(),
+            },
+            material.decoy_inputs.clone(),
+            &seed,
+        );
+        let other_pseudo_commitment = material.true_input.random_pseudo_commitment(&mut rng);
+
+        let nonces = seeded_nonces(&seed, b"message", 0, material, &pseudo_commitment);
+        assert_eq!(
+            nonces,
+            seeded_nonces(&seed, b"message", 0, material, &pseudo_commitment)
+        );
+        assert_ne!(
+            nonces.0,
+            seeded_nonces(&seed, b"message", 0, &other_key, &pseudo_commitment).0
+        );
+        assert_ne!(
+            nonces.0,
+            seeded_nonces(&seed, b"message", 0, material, &other_pseudo_commitment).0
+        );
+    }
+
+    #[test]
+    fn test_ringct_aggregated_range_proof() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let mut ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+
+        // Three outputs are padded to a four party proof
+        ring_ct.outputs = (0..3)
+            .map(|amount| Output {
+                public_key: G1Projective::random(&mut rng).to_affine(),
+                amount,
+                shared_secret: None,
+            })
+            .collect();
+
+        let (signed_tx, revealed_output_commitments) =
+            ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        assert_eq!(signed_tx.outputs.len(), 3);
+        assert_eq!(revealed_output_commitments.len(), 3);
+
+        let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
         assert!(signed_tx.verify(&public_commitments).is_ok());
+
+        // The proof is bound to every output commitment
+        let mut bad_tx = signed_tx.clone();
+        bad_tx.outputs.swap(0, 2);
+        assert!(bad_tx.verify(&public_commitments).is_err());
+    }
+
+    #[test]
+    fn test_ringct_rejects_invalid_points() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+
+        let (signed_tx, _revealed_output_commitments) =
+            ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
+
+        let mut bad_tx = signed_tx.clone();
+        bad_tx.outputs[0].commitment = G1Affine::identity();
+        assert_eq!(
+            bad_tx.verify(&public_commitments),
+            Err(Error::OutputCommitmentIsIdentity.at_output(0))
+        );
+
+        let mut bad_tx = signed_tx.clone();
+        bad_tx.outputs[0].commitment = crate::tests::point_outside_subgroup();
+        assert_eq!(
+            bad_tx.verify(&public_commitments),
+            Err(Error::OutputCommitmentNotInSubgroup.at_output(0))
+        );
+
+        let mut bad_tx = signed_tx;
+        bad_tx.mlsags[0].pseudo_commitment = crate::tests::point_outside_subgroup();
+        assert_eq!(
+            bad_tx.verify(&public_commitments),
+            Err(Error::PseudoCommitmentNotInSubgroup.at_input(0))
+        );
+    }
+
+    #[test]
+    fn test_ringct_verification_report() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let mut ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+        ring_ct.inputs.push(
+            test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng)
+                .inputs
+                .remove(0),
+        );
+        ring_ct.outputs[0].amount = 6;
+
+        let (signed_tx, _) = ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
+        assert!(signed_tx
+            .verification_report(&public_commitments)
+            .is_valid());
+
+        // Wrong number of rings
+        assert_eq!(
+            signed_tx.verify(&public_commitments[..1]),
+            Err(Error::RingCountMismatch {
+                expected: 2,
+                found: 1
+            })
+        );
+
+        // A wrong commitment for a ring member points at the input and the member
+        let mut bad_commitments = public_commitments.clone();
+        bad_commitments[1][2] = G1Projective::random(&mut rng).to_affine();
+        assert_eq!(
+            signed_tx.verify(&bad_commitments),
+            Err(Error::InvalidHiddenCommitmentInRing
+                .at_ring_member(2)
+                .at_input(1))
+        );
+
+        // The report lists every failure, the first one being what verify returns
+        let mut bad_tx = signed_tx;
+        bad_tx.outputs[0].commitment = G1Affine::identity();
+        bad_tx.mlsags[0].pseudo_commitment = crate::tests::point_outside_subgroup();
+        let report = bad_tx.verification_report(&bad_commitments);
+        assert!(!report.is_valid());
+        assert_eq!(
+            report.failures[..3],
+            [
+                Error::OutputCommitmentIsIdentity.at_output(0),
+                Error::PseudoCommitmentNotInSubgroup.at_input(0),
+                Error::InvalidHiddenCommitmentInRing
+                    .at_ring_member(2)
+                    .at_input(1),
+            ]
+        );
+        assert_eq!(
+            bad_tx.verify(&bad_commitments),
+            Err(report.failures[0].clone())
+        );
+    }
+
+    #[test]
+    fn test_ringct_bytes_round_trip() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+
+        let (signed_tx, _revealed_output_commitments) =
+            ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+
+        let bytes = signed_tx.to_bytes();
+        let decoded = RingCtTransaction::from_bytes(&bytes).expect("Failed to decode");
+        assert_eq!(decoded.to_bytes(), bytes);
+        assert_eq!(decoded.hash(), signed_tx.hash());
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4..6].copy_from_slice(&(WIRE_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            RingCtTransaction::from_bytes(&wrong_version).unwrap_err(),
+            Error::UnsupportedWireVersion(WIRE_VERSION + 1)
+        );
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] ^= 0xff;
+        assert_eq!(
+            RingCtTransaction::from_bytes(&wrong_magic).unwrap_err(),
+            Error::InvalidWireMagic
+        );
+
+        assert_eq!(
+            RingCtTransaction::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            Error::TruncatedInput
+        );
+
+        // Too many outputs are rejected before any range proof generators are needed
+        let mut too_many_outputs = signed_tx.clone();
+        too_many_outputs.outputs = vec![signed_tx.outputs[0].clone(); MAX_OUTPUTS + 1];
+        assert_eq!(
+            RingCtTransaction::from_bytes(&too_many_outputs.to_bytes()).unwrap_err(),
+            Error::TooManyOutputs
+        );
+        let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
+        assert_eq!(
+            too_many_outputs.verify(&public_commitments),
+            Err(Error::TooManyOutputs)
+        );
+    }
+
+    #[test]
+    fn test_ringct_verify_rejects_invalid_structure() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+        let (signed_tx, _revealed_output_commitments) =
+            ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        let public_commitments = ledger.public_commitments(&signed_tx).unwrap();
+
+        let check = |tx: &RingCtTransaction, public_commitments: &[Vec<G1Affine>], err| {
+            assert_eq!(tx.verify(public_commitments), Err(err));
+            assert_eq!(
+                crate::verify_batch(&[(tx, public_commitments)]),
+                Err(Error::InvalidTransactionsInBatch(vec![0]))
+            );
+        };
+
+        let mut no_inputs = signed_tx.clone();
+        no_inputs.mlsags.clear();
+        check(&no_inputs, &[], Error::NoInputs);
+
+        let mut no_outputs = signed_tx.clone();
+        no_outputs.outputs.clear();
+        check(&no_outputs, &public_commitments, Error::NoOutputs);
+
+        // Verification stops there, no ring member is hashed
+        let cache = HashToCurveCache::new(64);
+        assert_eq!(
+            no_outputs.verify_with_cache(&public_commitments, &cache),
+            Err(Error::NoOutputs)
+        );
+        assert!(cache.is_empty());
+
+        let mut small_ring = signed_tx.clone();
+        small_ring.mlsags[0].ring.truncate(1);
+        small_ring.mlsags[0].r.truncate(1);
+        let small_ring_commitments = vec![public_commitments[0][..1].to_vec()];
+        check(&small_ring, &small_ring_commitments, Error::RingTooSmall);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_ringct_serde_round_trip() {
+        let mut rng = OsRng;
+        let mut ledger = InMemoryLedger::default();
+        let ring_ct = test_material(&mut ledger, RingSignatureScheme::Mlsag, &mut rng);
+
+        let (signed_tx, revealed_output_commitments) =
+            ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+
+        let json = serde_json::to_string(&signed_tx).expect("Failed to serialize to json");
+        let from_json: RingCtTransaction =
+            serde_json::from_str(&json).expect("Failed to deserialize from json");
+        assert_eq!(from_json.to_bytes(), signed_tx.to_bytes());
+
+        // points are compressed and hex encoded in human readable formats
+        let key_image_hex = hex::encode(signed_tx.mlsags[0].key_image.to_compressed());
+        assert!(json.contains(&key_image_hex));
+
+        let bin = bincode::serialize(&signed_tx).expect("Failed to serialize to bincode");
+        let from_bin: RingCtTransaction =
+            bincode::deserialize(&bin).expect("Failed to deserialize from bincode");
+        assert_eq!(from_bin.to_bytes(), signed_tx.to_bytes());
+
+        let revealed = &revealed_output_commitments[0];
+        let json = serde_json::to_string(revealed).expect("Failed to serialize to json");
+        assert!(json.contains(&hex::encode(revealed.blinding.to_bytes_le())));
+        let from_json: RevealedCommitment =
+            serde_json::from_str(&json).expect("Failed to deserialize from json");
+        assert_eq!(from_json.value, revealed.value);
+        assert_eq!(from_json.blinding, revealed.blinding);
     }
 }
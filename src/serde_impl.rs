@@ -0,0 +1,234 @@
+//! Serde helpers for the curve types we embed in our public structs.
+//!
+//! G1 points are encoded as 48 byte compressed points and scalars as their
+//! canonical 32 byte little-endian representation. Human readable formats
+//! (e.g. JSON) get these bytes hex encoded, binary formats (e.g. bincode)
+//! get them as raw bytes.
+//!
+//! The submodules are meant to be used through `#[serde(with = "...")]`.
+
+use std::fmt;
+
+use blstrs::{G1Affine, Scalar};
+use serde::{
+    de::{self, Error as _, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    encoding::{G1_COMPRESSED_LEN, SCALAR_LEN},
+    stealth::ENCRYPTED_AMOUNT_LEN,
+    EncryptedAmount,
+};
+
+fn serialize_bytes<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+    if s.is_human_readable() {
+        s.serialize_str(&hex::encode(bytes))
+    } else {
+        s.serialize_bytes(bytes)
+    }
+}
+
+fn deserialize_bytes<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+    if d.is_human_readable() {
+        let s = String::deserialize(d)?;
+        hex::decode(s).map_err(D::Error::custom)
+    } else {
+        d.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+fn deserialize_array<'de, D: Deserializer<'de>, const N: usize>(d: D) -> Result<[u8; N], D::Error> {
+    deserialize_bytes(d)?
+        .try_into()
+        .map_err(|b: Vec<u8>| D::Error::custom(format!("expected {} bytes, got {}", N, b.len())))
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a byte array")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(v)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut v = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(b) = seq.next_element()? {
+            v.push(b);
+        }
+        Ok(v)
+    }
+}
+
+/// Wrapper giving a G1 point our serde representation
+struct Point(G1Affine);
+
+impl Serialize for Point {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&self.0.to_compressed(), s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Point {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let bytes = deserialize_array::<D, G1_COMPRESSED_LEN>(d)?;
+        Option::from(G1Affine::from_compressed(&bytes))
+            .map(Point)
+            .ok_or_else(|| D::Error::custom("bytes do not encode a valid G1 point"))
+    }
+}
+
+/// Wrapper giving a scalar our serde representation
+struct ScalarLe(Scalar);
+
+impl Serialize for ScalarLe {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&self.0.to_bytes_le(), s)
+    }
+}
+
+impl<'de> Deserialize<'de> for ScalarLe {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let bytes = deserialize_array::<D, SCALAR_LEN>(d)?;
+        Option::from(Scalar::from_bytes_le(&bytes))
+            .map(ScalarLe)
+            .ok_or_else(|| D::Error::custom("bytes do not encode a canonical scalar"))
+    }
+}
+
+impl Serialize for EncryptedAmount {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&self.to_bytes(), s)
+    }
+}
+
+impl<'de> Deserialize<'de> for EncryptedAmount {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let bytes = deserialize_array::<D, ENCRYPTED_AMOUNT_LEN>(d)?;
+        Ok(EncryptedAmount::from_bytes(bytes))
+    }
+}
+
+pub(crate) mod point {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(p: &G1Affine, s: S) -> Result<S::Ok, S::Error> {
+        Point(*p).serialize(s)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<G1Affine, D::Error> {
+        Ok(Point::deserialize(d)?.0)
+    }
+}
+
+pub(crate) mod optional_point {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(p: &Option<G1Affine>, s: S) -> Result<S::Ok, S::Error> {
+        p.map(Point).serialize(s)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<Option<G1Affine>, D::Error> {
+        Ok(Option::<Point>::deserialize(d)?.map(|p| p.0))
+    }
+}
+
+pub(crate) mod points {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(points: &[G1Affine], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(points.iter().map(|p| Point(*p)))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<G1Affine>, D::Error> {
+        let points = Vec::<Point>::deserialize(d)?;
+        Ok(points.into_iter().map(|p| p.0).collect())
+    }
+}
+
+pub(crate) mod point_pairs {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        pairs: &[(G1Affine, G1Affine)],
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        s.collect_seq(pairs.iter().map(|(a, b)| (Point(*a), Point(*b))))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<Vec<(G1Affine, G1Affine)>, D::Error> {
+        let pairs = Vec::<(Point, Point)>::deserialize(d)?;
+        Ok(pairs.into_iter().map(|(a, b)| (a.0, b.0)).collect())
+    }
+}
+
+pub(crate) mod scalar {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(x: &Scalar, s: S) -> Result<S::Ok, S::Error> {
+        ScalarLe(*x).serialize(s)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Scalar, D::Error> {
+        Ok(ScalarLe::deserialize(d)?.0)
+    }
+}
+
+pub(crate) mod scalars {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(scalars: &[Scalar], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(scalars.iter().map(|x| ScalarLe(*x)))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Scalar>, D::Error> {
+        let scalars = Vec::<ScalarLe>::deserialize(d)?;
+        Ok(scalars.into_iter().map(|x| x.0).collect())
+    }
+}
+
+pub(crate) mod scalar_pairs {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        pairs: &[(Scalar, Scalar)],
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        s.collect_seq(pairs.iter().map(|(a, b)| (ScalarLe(*a), ScalarLe(*b))))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<Vec<(Scalar, Scalar)>, D::Error> {
+        let pairs = Vec::<(ScalarLe, ScalarLe)>::deserialize(d)?;
+        Ok(pairs.into_iter().map(|(a, b)| (a.0, b.0)).collect())
+    }
+}
+
+pub(crate) mod range_proof {
+    use bulletproofs::RangeProof;
+
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(p: &RangeProof, s: S) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&p.to_bytes(), s)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<RangeProof, D::Error> {
+        RangeProof::from_bytes(&deserialize_bytes(d)?).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,301 @@
+//! Registries of spent key images, to reject double spends.
+//!
+//! Every input of a transaction reveals the key image of the output it spends,
+//! so an output is spent twice exactly when a key image shows up twice.
+//! `RingCtTransaction::verify_and_spend` checks a transaction against a
+//! `SpentKeyImages` store and records its key images once it is known valid.
+
+use std::{
+    collections::BTreeSet,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use blstrs::G1Affine;
+use tiny_keccak::{Hasher, Sha3};
+
+use crate::{encoding::G1_COMPRESSED_LEN, Error, InMemoryLedger, Ledger, Result};
+
+pub trait SpentKeyImages {
+    fn contains(&self, key_image: &G1Affine) -> Result<bool>;
+
+    /// Records every key image in `key_images`, either all of them or none.
+    fn insert_all(&mut self, key_images: &[G1Affine]) -> Result<()>;
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct InMemorySpentKeyImages {
+    key_images: BTreeSet<[u8; 48]>, // compressed key images
+}
+
+impl SpentKeyImages for InMemorySpentKeyImages {
+    fn contains(&self, key_image: &G1Affine) -> Result<bool> {
+        Ok(self.key_images.contains(&key_image.to_compressed()))
+    }
+
+    fn insert_all(&mut self, key_images: &[G1Affine]) -> Result<()> {
+        self.key_images
+            .extend(key_images.iter().map(G1Affine::to_compressed));
+        Ok(())
+    }
+}
+
+impl SpentKeyImages for InMemoryLedger {
+    fn contains(&self, key_image: &G1Affine) -> Result<bool> {
+        Ok(self.is_spent(key_image))
+    }
+
+    fn insert_all(&mut self, key_images: &[G1Affine]) -> Result<()> {
+        for key_image in key_images {
+            self.mark_spent(*key_image);
+        }
+        Ok(())
+    }
+}
+
+/// An append-only file of spent key images, mirrored in memory.
+///
+/// Each call to `insert_all` appends one record:
+///   key image count (u32 LE) | first 4 bytes of SHA3-256 of the count |
+///   compressed key images | SHA3-256 of the former
+/// and syncs it to disk. The count has its own checksum, so a record whose
+/// count is intact but whose key images run past the end of the file was cut
+/// short by a crash, and can only be the last one. It is discarded when the
+/// file is opened again so a transaction's key images are stored all or none.
+/// Any other damage fails `open` with `Error::CorruptSpentKeyImages` rather
+/// than losing the records after it.
+///
+/// If an append fails the file is truncated back to the last record. When
+/// even that fails the store refuses further appends until it is reopened.
+pub struct FileSpentKeyImages {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    failed: bool,
+    key_images: BTreeSet<[u8; 48]>,
+}
+
+impl FileSpentKeyImages {
+    /// Opens the registry at `path`, creating the file if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(io_error)?;
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).map_err(io_error)?;
+
+        let mut key_images = BTreeSet::new();
+        let mut complete_len = 0;
+        while complete_len < bytes.len() {
+            match read_record(&bytes[complete_len..]) {
+                Record::Complete(record) => {
+                    key_images.extend(record[HEADER_LEN..].chunks_exact(G1_COMPRESSED_LEN).map(
+                        |chunk| {
+                            let mut key_image = [0u8; 48];
+                            key_image.copy_from_slice(chunk);
+                            key_image
+                        },
+                    ));
+                    complete_len += record.len() + CHECKSUM_LEN;
+                }
+                Record::Torn => {
+                    // drop the torn record so later appends start on a record boundary
+                    file.set_len(complete_len as u64).map_err(io_error)?;
+                    file.sync_data().map_err(io_error)?;
+                    break;
+                }
+                Record::Corrupt => return Err(Error::CorruptSpentKeyImages(complete_len as u64)),
+            }
+        }
+
+        Ok(Self {
+            path,
+            file,
+            len: complete_len as u64,
+            failed: false,
+            key_images,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+const COUNT_CHECKSUM_LEN: usize = 4;
+const HEADER_LEN: usize = 4 + COUNT_CHECKSUM_LEN;
+const CHECKSUM_LEN: usize = 32;
+
+/// The record at the start of some bytes, see `read_record`
+enum Record<'a> {
+    /// The header and key images, whose checksum matched
+    Complete(&'a [u8]),
+    /// A record running past the end of the bytes, as a crash leaves it
+    Torn,
+    Corrupt,
+}
+
+/// Reads the record at the start of `bytes`.
+///
+/// Only a record whose header is short, or whose header is intact but whose
+/// key images or checksum are short, is torn. A short header can only be at
+/// the end of the file, since any record after it would fill it out.
+fn read_record(bytes: &[u8]) -> Record<'_> {
+    let Some(header) = bytes.get(..HEADER_LEN) else {
+        return Record::Torn;
+    };
+    let count: [u8; 4] = header[..4].try_into().unwrap();
+    if record_header(u32::from_le_bytes(count)) != header {
+        return Record::Corrupt;
+    }
+    let Some(len) = (u32::from_le_bytes(count) as usize)
+        .checked_mul(G1_COMPRESSED_LEN)
+        .and_then(|len| len.checked_add(HEADER_LEN))
+    else {
+        return Record::Corrupt;
+    };
+    match (bytes.get(..len), bytes.get(len..len + CHECKSUM_LEN)) {
+        (Some(record), Some(checksum)) if record_checksum(record) == checksum => {
+            Record::Complete(record)
+        }
+        (Some(_), Some(_)) => Record::Corrupt,
+        _ => Record::Torn,
+    }
+}
+
+/// The key image count followed by the start of its SHA3-256
+fn record_header(count: u32) -> [u8; HEADER_LEN] {
+    let count = count.to_le_bytes();
+    let mut header = [0u8; HEADER_LEN];
+    header[..4].copy_from_slice(&count);
+    header[4..].copy_from_slice(&record_checksum(&count)[..COUNT_CHECKSUM_LEN]);
+    header
+}
+
+fn record_checksum(record: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let mut sha3 = Sha3::v256();
+    sha3.update(record);
+    let mut checksum = [0u8; CHECKSUM_LEN];
+    sha3.finalize(&mut checksum);
+    checksum
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::Io(e.to_string())
+}
+
+impl SpentKeyImages for FileSpentKeyImages {
+    fn contains(&self, key_image: &G1Affine) -> Result<bool> {
+        Ok(self.key_images.contains(&key_image.to_compressed()))
+    }
+
+    fn insert_all(&mut self, key_images: &[G1Affine]) -> Result<()> {
+        if self.failed {
+            return Err(Error::Io(format!(
+                "an earlier append to {} could not be undone, reopen it",
+                self.path.display()
+            )));
+        }
+
+        let mut record =
+            Vec::with_capacity(HEADER_LEN + key_images.len() * G1_COMPRESSED_LEN + CHECKSUM_LEN);
+        record.extend(record_header(key_images.len() as u32));
+        for key_image in key_images {
+            record.extend(key_image.to_compressed());
+        }
+        record.extend(record_checksum(&record));
+
+        let appended = self
+            .file
+            .write_all(&record)
+            .and_then(|()| self.file.sync_data());
+        if let Err(e) = appended {
+            // Remove whatever part of the record made it to the file, so the
+            // next append does not land after a torn record
+            let undone = self
+                .file
+                .set_len(self.len)
+                .and_then(|()| self.file.sync_data());
+            self.failed = undone.is_err();
+            return Err(io_error(e));
+        }
+        self.len += record.len() as u64;
+
+        self.key_images
+            .extend(key_images.iter().map(G1Affine::to_compressed));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::{
+        group::{Curve, Group},
+        G1Projective,
+    };
+    use rand_core::{OsRng, RngCore};
+
+    use super::*;
+
+    #[test]
+    fn test_file_spent_key_images() {
+        let path = std::env::temp_dir().join(format!(
+            "blst-ringct-spent-{}-{}",
+            std::process::id(),
+            OsRng.next_u64()
+        ));
+        let key_images = Vec::from_iter((0..3).map(|_| G1Projective::random(OsRng).to_affine()));
+
+        let mut store = FileSpentKeyImages::open(&path).unwrap();
+        store.insert_all(&key_images[..2]).unwrap();
+        assert!(store.contains(&key_images[1]).unwrap());
+        drop(store);
+
+        // Simulate a crash part way through appending the next record
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&record_header(1)).unwrap();
+        file.write_all(&key_images[2].to_compressed()[..10])
+            .unwrap();
+        drop(file);
+
+        let mut store = FileSpentKeyImages::open(&path).unwrap();
+        assert!(store.contains(&key_images[0]).unwrap());
+        assert!(store.contains(&key_images[1]).unwrap());
+        assert!(!store.contains(&key_images[2]).unwrap());
+
+        // The torn record is dropped, so new records can be read back
+        store.insert_all(&key_images[2..]).unwrap();
+        drop(store);
+        let mut store = FileSpentKeyImages::open(&path).unwrap();
+        assert!(key_images.iter().all(|k| store.contains(k).unwrap()));
+
+        // An append that fails and can not be undone stops further appends
+        store.file = File::open(&path).unwrap();
+        assert!(store.insert_all(&key_images[..1]).is_err());
+        assert!(store.failed);
+        assert!(store.insert_all(&key_images[..1]).is_err());
+        drop(store);
+
+        // Damage before the last record is reported instead of truncated,
+        // whether it hits a key image or the count of the first record
+        let bytes = std::fs::read(&path).unwrap();
+        for (offset, flip) in [(HEADER_LEN + 2, 0xff), (0, 0x01), (0, 0x80)] {
+            let mut damaged = bytes.clone();
+            damaged[offset] ^= flip;
+            std::fs::write(&path, &damaged).unwrap();
+            assert_eq!(
+                FileSpentKeyImages::open(&path).err(),
+                Some(Error::CorruptSpentKeyImages(0))
+            );
+            assert_eq!(std::fs::read(&path).unwrap(), damaged);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,309 @@
+//! Stealth addresses, so that payments to a recipient can not be linked.
+//!
+//! A recipient publishes a `StealthAddress` (A, B) = (a G, b G) made of a view
+//! key and a spend key. For every transaction the sender picks a fresh
+//! `TransactionKey` r, publishes R = r G with the transaction, and pays output
+//! `i` to the one-time key
+//!
+//!   P_i = Hs(r A || i) G + B
+//!
+//! Since r A = a R, the recipient can recognise the output with their view key
+//! and recover the one-time secret key x_i = Hs(a R || i) + b with their spend key.
+//!
+//! The amount and blinding of each output are sent along encrypted under the
+//! same shared secret, see `EncryptedAmount`.
+
+use blstrs::{
+    group::{ff::Field, Curve, Group},
+    G1Affine, G1Projective, Scalar,
+};
+use bulletproofs::PedersenGens;
+use rand_core::RngCore;
+
+use crate::{
+    encoding::{Reader, SCALAR_LEN},
+    hash_rng::HashRng,
+    mlsag::hash_to_scalar,
+    Error, Output, Result, RevealedCommitment, TrueInput,
+};
+
+const ONE_TIME_KEY_DOMAIN: &[u8] = b"blst-ringct-stealth-one-time-key";
+const AMOUNT_KEY_DOMAIN: &[u8] = b"blst-ringct-stealth-amount-key";
+
+pub(crate) const ENCRYPTED_AMOUNT_LEN: usize = 8 + SCALAR_LEN; // value, blinding
+
+/// The secret view and spend keys of a recipient
+#[derive(Clone)]
+pub struct StealthSecretKeys {
+    pub view: Scalar,
+    pub spend: Scalar,
+}
+
+impl StealthSecretKeys {
+    pub fn random(mut rng: impl RngCore) -> Self {
+        Self {
+            view: Scalar::random(&mut rng),
+            spend: Scalar::random(&mut rng),
+        }
+    }
+
+    pub fn address(&self) -> StealthAddress {
+        StealthAddress {
+            view: (G1Projective::generator() * self.view).to_affine(),
+            spend: (G1Projective::generator() * self.spend).to_affine(),
+        }
+    }
+
+    /// The secret shared with the sender of a transaction, a R
+    pub fn shared_secret(&self, tx_public_key: &G1Affine) -> SharedSecret {
+        SharedSecret((tx_public_key * self.view).to_affine())
+    }
+
+    /// The secret key of output `index` of a transaction, Hs(a R || i) + b
+    pub fn one_time_secret_key(&self, shared_secret: &SharedSecret, index: usize) -> Scalar {
+        shared_secret.scalar(index) + self.spend
+    }
+
+    /// Returns the one-time secret key of `output_public_key` if it was paid
+    /// to us as output `index` of the transaction with public key `tx_public_key`.
+    pub fn recover_one_time_secret_key(
+        &self,
+        tx_public_key: &G1Affine,
+        index: usize,
+        output_public_key: &G1Affine,
+    ) -> Option<Scalar> {
+        let shared_secret = self.shared_secret(tx_public_key);
+        let expected = self.address().one_time_public_key(&shared_secret, index);
+        if expected == *output_public_key {
+            Some(self.one_time_secret_key(&shared_secret, index))
+        } else {
+            None
+        }
+    }
+
+    /// Builds the `TrueInput` needed to spend an output we own
+    pub fn true_input(
+        &self,
+        tx_public_key: &G1Affine,
+        index: usize,
+        output_public_key: &G1Affine,
+        revealed_commitment: RevealedCommitment,
+    ) -> Option<TrueInput> {
+        self.recover_one_time_secret_key(tx_public_key, index, output_public_key)
+            .map(|secret_key| TrueInput {
+                secret_key,
+                revealed_commitment,
+            })
+    }
+}
+
+/// The public view and spend keys a recipient hands out to get paid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StealthAddress {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::point"))]
+    pub view: G1Affine,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::point"))]
+    pub spend: G1Affine,
+}
+
+impl StealthAddress {
+    /// The one-time public key of output `index`, Hs(s || i) G + B
+    pub fn one_time_public_key(&self, shared_secret: &SharedSecret, index: usize) -> G1Affine {
+        (G1Projective::generator() * shared_secret.scalar(index) + self.spend).to_affine()
+    }
+}
+
+/// The per transaction secret r chosen by the sender
+#[derive(Clone)]
+pub struct TransactionKey {
+    pub(crate) secret: Scalar,
+}
+
+impl TransactionKey {
+    pub fn random(rng: impl RngCore) -> Self {
+        Self {
+            secret: Scalar::random(rng),
+        }
+    }
+
+    /// R = r G, published with the transaction in `RingCtTransaction::tx_public_key`
+    pub fn public_key(&self) -> G1Affine {
+        (G1Projective::generator() * self.secret).to_affine()
+    }
+
+    /// The secret shared with the owner of `address`, r A
+    pub fn shared_secret(&self, address: &StealthAddress) -> SharedSecret {
+        SharedSecret((address.view * self.secret).to_affine())
+    }
+
+    /// Builds output `index` of the transaction, paying `amount` to `address`.
+    /// `index` must be the position of the output in `RingCtMaterial::outputs`.
+    pub fn output(&self, address: &StealthAddress, index: usize, amount: u64) -> Output {
+        let shared_secret = self.shared_secret(address);
+        Output {
+            public_key: address.one_time_public_key(&shared_secret, index),
+            amount,
+            shared_secret: Some(shared_secret),
+        }
+    }
+}
+
+/// The Diffie-Hellman secret r A = a R known to both sender and recipient
+#[derive(Clone, PartialEq, Eq)]
+pub struct SharedSecret(pub(crate) G1Affine);
+
+impl SharedSecret {
+    /// Hs(s || i)
+    fn scalar(&self, index: usize) -> Scalar {
+        hash_to_scalar(&[
+            ONE_TIME_KEY_DOMAIN,
+            &self.0.to_compressed(),
+            &(index as u64).to_le_bytes(),
+        ])
+    }
+
+    /// The keystream used to encrypt the amount of output `index`
+    fn amount_keystream(&self, index: usize) -> [u8; ENCRYPTED_AMOUNT_LEN] {
+        let mut keystream = [0u8; ENCRYPTED_AMOUNT_LEN];
+        HashRng::new(
+            AMOUNT_KEY_DOMAIN,
+            &[&self.0.to_compressed(), &(index as u64).to_le_bytes()],
+        )
+        .fill_bytes(&mut keystream);
+        keystream
+    }
+}
+
+/// The value and blinding of an output, encrypted for its recipient.
+///
+/// The plaintext is XORed with a keystream derived from the shared secret and
+/// the output index. There is no MAC, instead the recipient checks that the
+/// decrypted amount opens the output commitment, see `OutputProof::decrypt_amount`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncryptedAmount(pub(crate) [u8; ENCRYPTED_AMOUNT_LEN]);
+
+impl EncryptedAmount {
+    pub fn encrypt(
+        revealed_commitment: &RevealedCommitment,
+        shared_secret: &SharedSecret,
+        index: usize,
+    ) -> Self {
+        let mut bytes = [0u8; ENCRYPTED_AMOUNT_LEN];
+        bytes.copy_from_slice(&revealed_commitment.to_bytes());
+        xor(&mut bytes, &shared_secret.amount_keystream(index));
+        Self(bytes)
+    }
+
+    /// Decrypts the amount, this does not check it against the commitment.
+    pub fn decrypt(
+        &self,
+        shared_secret: &SharedSecret,
+        index: usize,
+    ) -> Result<RevealedCommitment> {
+        let mut bytes = self.0;
+        xor(&mut bytes, &shared_secret.amount_keystream(index));
+
+        let mut reader = Reader::new(&bytes);
+        let value = u64::from_le_bytes(reader.read_array()?);
+        let blinding = reader.read_scalar()?;
+        reader.finish()?;
+        Ok(RevealedCommitment { value, blinding })
+    }
+
+    pub fn to_bytes(&self) -> [u8; ENCRYPTED_AMOUNT_LEN] {
+        self.0
+    }
+
+    pub fn from_bytes(bytes: [u8; ENCRYPTED_AMOUNT_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    /// Decrypts the amount and checks that it opens `commitment`
+    pub(crate) fn open(
+        &self,
+        shared_secret: &SharedSecret,
+        index: usize,
+        commitment: &G1Affine,
+        pc_gens: &PedersenGens,
+    ) -> Result<RevealedCommitment> {
+        let revealed_commitment = self.decrypt(shared_secret, index)?;
+        if revealed_commitment.commit(pc_gens).to_affine() == *commitment {
+            Ok(revealed_commitment)
+        } else {
+            Err(Error::EncryptedAmountDoesNotOpenCommitment)
+        }
+    }
+}
+
+fn xor(bytes: &mut [u8], keystream: &[u8]) {
+    for (b, k) in bytes.iter_mut().zip(keystream) {
+        *b ^= k;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn test_one_time_keys() {
+        let recipient = StealthSecretKeys::random(OsRng);
+        let address = recipient.address();
+        let tx_key = TransactionKey::random(OsRng);
+
+        let outputs = Vec::from_iter((0..2).map(|i| tx_key.output(&address, i, 10)));
+        assert!(outputs[0].shared_secret == Some(recipient.shared_secret(&tx_key.public_key())));
+
+        // Paying the same address twice gives unlinkable keys
+        assert_ne!(outputs[0].public_key, outputs[1].public_key);
+        assert_ne!(outputs[0].public_key, address.spend);
+
+        for (i, output) in outputs.iter().enumerate() {
+            let secret_key = recipient
+                .recover_one_time_secret_key(&tx_key.public_key(), i, &output.public_key)
+                .expect("Output should be ours");
+            assert_eq!(
+                (G1Projective::generator() * secret_key).to_affine(),
+                output.public_key
+            );
+        }
+
+        // Outputs are bound to their index and to the recipient
+        assert!(recipient
+            .recover_one_time_secret_key(&tx_key.public_key(), 1, &outputs[0].public_key)
+            .is_none());
+        let other = StealthSecretKeys::random(OsRng);
+        assert!(other
+            .recover_one_time_secret_key(&tx_key.public_key(), 0, &outputs[0].public_key)
+            .is_none());
+    }
+
+    #[test]
+    fn test_encrypted_amount() {
+        let pc_gens = PedersenGens::default();
+        let recipient = StealthSecretKeys::random(OsRng);
+        let tx_key = TransactionKey::random(OsRng);
+        let shared_secret = tx_key.shared_secret(&recipient.address());
+
+        let revealed_commitment = RevealedCommitment::from_value(42, OsRng);
+        let commitment = revealed_commitment.commit(&pc_gens).to_affine();
+        let encrypted = EncryptedAmount::encrypt(&revealed_commitment, &shared_secret, 1);
+
+        let recipient_secret = recipient.shared_secret(&tx_key.public_key());
+        let decrypted = encrypted
+            .open(&recipient_secret, 1, &commitment, &pc_gens)
+            .expect("Failed to decrypt");
+        assert_eq!(decrypted.value, 42);
+        assert_eq!(decrypted.blinding, revealed_commitment.blinding);
+
+        // The wrong index or key gives garbage that does not open the commitment
+        assert!(encrypted
+            .open(&recipient_secret, 0, &commitment, &pc_gens)
+            .is_err());
+        let other = StealthSecretKeys::random(OsRng).shared_secret(&tx_key.public_key());
+        assert!(encrypted.open(&other, 1, &commitment, &pc_gens).is_err());
+    }
+}
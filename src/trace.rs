@@ -0,0 +1,43 @@
+//! Instrumentation behind the `tracing` feature.
+//!
+//! Without the feature these helpers just run their closure, so call sites do
+//! not need their own `cfg` attributes.
+
+use crate::Result;
+
+/// Runs one phase of signing or verification, `name` being e.g. "range_proof".
+///
+/// With the `tracing` feature the phase runs in a debug span and its duration
+/// is recorded in a trace event when it completes.
+#[inline]
+pub(crate) fn phase<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "tracing")]
+    {
+        let _span = tracing::debug_span!("phase", phase = name).entered();
+        let start = std::time::Instant::now();
+        let out = f();
+        tracing::trace!(
+            phase = name,
+            elapsed_us = start.elapsed().as_micros() as u64,
+            "phase complete"
+        );
+        out
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = name;
+        f()
+    }
+}
+
+/// Runs a verification check as a `phase`, emitting a debug event naming the
+/// check and the error when it fails.
+#[inline]
+pub(crate) fn check(name: &'static str, f: impl FnOnce() -> Result<()>) -> Result<()> {
+    let result = phase(name, f);
+    #[cfg(feature = "tracing")]
+    if let Err(e) = &result {
+        tracing::debug!(check = name, error = %e, "check failed");
+    }
+    result
+}
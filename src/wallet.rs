@@ -0,0 +1,288 @@
+//! Finds the outputs paid to a stealth address.
+//!
+//! A `Wallet` scans transactions with the recipient's view key, decrypts the
+//! amount of every output addressed to them and keeps the `TrueInput`s needed
+//! to spend those outputs later.
+//!
+//! The key image of every owned output is computed up front. When a scanned
+//! transaction carries one of them the output has been spent, whichever device
+//! spent it.
+
+use std::collections::BTreeSet;
+
+use blstrs::{group::Curve, G1Affine};
+
+use crate::{
+    ringct::{checked_sum, RingCtTransaction},
+    Result, StealthAddress, StealthSecretKeys, TrueInput,
+};
+
+/// An output paid to the wallet
+#[derive(Clone)]
+pub struct OwnedOutput {
+    /// `RingCtTransaction::hash` of the transaction that created the output
+    pub tx_hash: [u8; 32],
+    /// The position of the output in the transaction
+    pub index: usize,
+    /// The one-time public key the output was paid to
+    pub public_key: G1Affine,
+    pub commitment: G1Affine,
+    pub true_input: TrueInput,
+    /// The key image that will appear in the transaction spending this output
+    pub key_image: G1Affine,
+    pub spent: bool,
+}
+
+impl OwnedOutput {
+    pub fn amount(&self) -> u64 {
+        self.true_input.revealed_commitment.value
+    }
+}
+
+pub struct Wallet {
+    keys: StealthSecretKeys,
+    address: StealthAddress,
+    outputs: Vec<OwnedOutput>,
+    seen_key_images: BTreeSet<[u8; 48]>, // compressed key images of every scanned transaction
+}
+
+impl Wallet {
+    pub fn new(keys: StealthSecretKeys) -> Self {
+        let address = keys.address();
+        Self {
+            keys,
+            address,
+            outputs: Default::default(),
+            seen_key_images: Default::default(),
+        }
+    }
+
+    pub fn address(&self) -> StealthAddress {
+        self.address
+    }
+
+    /// Scans every transaction in `txs`, see `Wallet::scan`
+    pub fn scan_all<'a>(&mut self, txs: impl IntoIterator<Item = &'a RingCtTransaction>) {
+        for tx in txs {
+            self.scan(tx);
+        }
+    }
+
+    /// Records the outputs of `tx` paid to this wallet and returns how many were
+    /// found, then marks the owned outputs that `tx` spends.
+    ///
+    /// Outputs whose encrypted amount is missing or does not open the output
+    /// commitment can not be spent, so they are skipped. Scanning the same
+    /// transaction twice does not record its outputs twice.
+    ///
+    /// `tx` is expected to be verified already: the outputs of an unverified
+    /// transaction may not be spendable and their amounts are not range proven.
+    pub fn scan(&mut self, tx: &RingCtTransaction) -> usize {
+        let found = self.scan_outputs(tx);
+        self.scan_key_images(tx);
+        found
+    }
+
+    fn scan_key_images(&mut self, tx: &RingCtTransaction) {
+        for key_image in tx.key_images() {
+            self.seen_key_images.insert(key_image.to_compressed());
+            for owned in self.outputs.iter_mut() {
+                if owned.key_image == key_image {
+                    owned.spent = true;
+                }
+            }
+        }
+    }
+
+    fn scan_outputs(&mut self, tx: &RingCtTransaction) -> usize {
+        let tx_public_key = match tx.tx_public_key {
+            Some(tx_public_key) => tx_public_key,
+            None => return 0,
+        };
+        let shared_secret = self.keys.shared_secret(&tx_public_key);
+        let tx_hash = tx.hash();
+
+        let mut found = 0;
+        for (index, output) in tx.outputs.iter().enumerate() {
+            if self.address.one_time_public_key(&shared_secret, index) != output.public_key()
+                || self.is_known(&output.public_key())
+            {
+                continue;
+            }
+
+            let revealed_commitment = match output.decrypt_amount(&shared_secret, index) {
+                Ok(revealed_commitment) => revealed_commitment,
+                Err(_) => continue,
+            };
+
+            let true_input = TrueInput {
+                secret_key: self.keys.one_time_secret_key(&shared_secret, index),
+                revealed_commitment,
+            };
+            let key_image = true_input.key_image().to_affine();
+
+            self.outputs.push(OwnedOutput {
+                tx_hash,
+                index,
+                public_key: output.public_key(),
+                commitment: output.commitment(),
+                true_input,
+                key_image,
+                // The spend may have been scanned before the output itself
+                spent: self.seen_key_images.contains(&key_image.to_compressed()),
+            });
+            found += 1;
+        }
+        found
+    }
+
+    fn is_known(&self, public_key: &G1Affine) -> bool {
+        self.outputs.iter().any(|o| o.public_key == *public_key)
+    }
+
+    /// Every output found so far, including spent ones
+    pub fn outputs(&self) -> &[OwnedOutput] {
+        &self.outputs
+    }
+
+    /// The outputs that have not been spent yet
+    pub fn unspent(&self) -> impl Iterator<Item = &OwnedOutput> {
+        self.outputs.iter().filter(|o| !o.spent)
+    }
+
+    /// The total amount of the unspent outputs, failing with
+    /// `Error::AmountOverflow` if it does not fit in a u64
+    pub fn balance(&self) -> Result<u64> {
+        checked_sum(self.unspent().map(OwnedOutput::amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blstrs::{
+        group::{ff::Field, Curve, Group},
+        G1Projective, Scalar,
+    };
+    use rand_core::OsRng;
+
+    use super::*;
+    use crate::{DecoyInput, MlsagMaterial, RevealedCommitment, RingCtMaterial, TransactionKey};
+
+    fn pay(recipients: &[(&StealthAddress, u64)]) -> RingCtTransaction {
+        let mut rng = OsRng;
+        let total = recipients.iter().map(|(_, amount)| amount).sum();
+        let true_input = TrueInput {
+            secret_key: Scalar::random(&mut rng),
+            revealed_commitment: RevealedCommitment::from_value(total, &mut rng),
+        };
+        let decoy_inputs = vec![DecoyInput {
+            public_key: G1Projective::random(&mut rng).to_affine(),
+            commitment: G1Projective::random(&mut rng).to_affine(),
+        }];
+
+        let tx_key = TransactionKey::random(&mut rng);
+        let ring_ct = RingCtMaterial {
+            inputs: vec![MlsagMaterial::new(true_input, decoy_inputs, &mut rng)],
+            outputs: recipients
+                .iter()
+                .enumerate()
+                .map(|(i, (address, amount))| tx_key.output(address, i, *amount))
+                .collect(),
+            tx_public_key: Some(tx_key.public_key()),
+            ..Default::default()
+        };
+        let (tx, _) = ring_ct.sign(&mut rng).expect("Failed to sign transaction");
+        tx
+    }
+
+    #[test]
+    fn test_wallet_scan() {
+        let mut wallet = Wallet::new(StealthSecretKeys::random(OsRng));
+        let other = StealthSecretKeys::random(OsRng).address();
+        let address = wallet.address();
+
+        let txs = vec![
+            pay(&[(&other, 5), (&address, 3)]),
+            pay(&[(&other, 1)]),
+            pay(&[(&address, 2), (&address, 4)]),
+        ];
+        wallet.scan_all(&txs);
+
+        assert_eq!(wallet.outputs().len(), 3);
+        assert_eq!(wallet.balance(), Ok(9));
+
+        let pc_gens = RingCtMaterial::pc_gens();
+        for owned in wallet.outputs() {
+            assert_eq!(owned.public_key, owned.true_input.public_key().to_affine());
+            assert_eq!(
+                owned.commitment,
+                owned
+                    .true_input
+                    .revealed_commitment
+                    .commit(&pc_gens)
+                    .to_affine()
+            );
+        }
+        assert_eq!(wallet.outputs()[0].tx_hash, txs[0].hash());
+        assert_eq!(wallet.outputs()[0].index, 1);
+
+        // Rescanning does not double count
+        assert_eq!(wallet.scan(&txs[2]), 0);
+        assert_eq!(wallet.balance(), Ok(9));
+
+        // Transactions without a tx public key have no stealth outputs
+        let mut tx = pay(&[(&address, 7)]);
+        tx.tx_public_key = None;
+        assert_eq!(wallet.scan(&tx), 0);
+    }
+
+    #[test]
+    fn test_wallet_detects_spends() {
+        let keys = StealthSecretKeys::random(OsRng);
+        let mut wallet = Wallet::new(keys.clone());
+        let address = wallet.address();
+        let other = StealthSecretKeys::random(OsRng).address();
+
+        let txs = vec![pay(&[(&address, 3)]), pay(&[(&address, 4)])];
+        wallet.scan_all(&txs);
+        assert_eq!(wallet.balance(), Ok(7));
+
+        // Another device with the same keys spends the first output
+        let owned = wallet.outputs()[0].clone();
+        let mut rng = OsRng;
+        let spend = RingCtMaterial {
+            inputs: vec![MlsagMaterial::new(
+                owned.true_input,
+                vec![DecoyInput {
+                    public_key: G1Projective::random(&mut rng).to_affine(),
+                    commitment: G1Projective::random(&mut rng).to_affine(),
+                }],
+                &mut rng,
+            )],
+            outputs: vec![TransactionKey::random(&mut rng).output(&other, 0, 3)],
+            ..Default::default()
+        };
+        let (spend_tx, _) = spend.sign(&mut rng).expect("Failed to sign transaction");
+
+        wallet.scan(&spend_tx);
+        assert!(wallet.outputs()[0].spent);
+        assert!(!wallet.outputs()[1].spent);
+        assert_eq!(wallet.balance(), Ok(4));
+        assert_eq!(wallet.unspent().count(), 1);
+
+        // A fresh wallet that sees the spend before the output still gets it right
+        let mut restored = Wallet::new(keys);
+        restored.scan(&spend_tx);
+        restored.scan_all(&txs);
+        assert!(restored.outputs()[0].spent);
+        assert_eq!(restored.balance(), Ok(4));
+    }
+
+    #[test]
+    fn test_wallet_balance_overflow() {
+        let mut wallet = Wallet::new(StealthSecretKeys::random(OsRng));
+        let address = wallet.address();
+        wallet.scan_all(&[pay(&[(&address, u64::MAX)]), pay(&[(&address, 1)])]);
+        assert_eq!(wallet.balance(), Err(crate::Error::AmountOverflow));
+    }
+}
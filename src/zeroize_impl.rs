@@ -0,0 +1,115 @@
+//! Wipes secret material from memory, enabled by the `zeroize` feature.
+//!
+//! `Scalar` and `G1Affine` do not implement `Zeroize`, so they are overwritten
+//! with a volatile write followed by a compiler fence, the way `zeroize` wipes
+//! its own types.
+
+use std::sync::atomic::{compiler_fence, Ordering};
+
+use blstrs::{
+    group::{ff::Field, prime::PrimeCurveAffine},
+    G1Affine, Scalar,
+};
+use zeroize::Zeroize;
+
+use crate::{
+    MlsagMaterial, MlsagSecrets, RevealedCommitment, SharedSecret, StealthSecretKeys,
+    TransactionKey, TrueInput,
+};
+
+fn wipe<T: Copy>(value: &mut T, zero: T) {
+    // SAFETY: `value` is a valid and aligned exclusive reference, and `T: Copy`
+    // so the overwritten value needs no drop.
+    unsafe { std::ptr::write_volatile(value, zero) };
+    compiler_fence(Ordering::SeqCst);
+}
+
+fn wipe_scalar(scalar: &mut Scalar) {
+    wipe(scalar, Scalar::zero());
+}
+
+impl Zeroize for RevealedCommitment {
+    fn zeroize(&mut self) {
+        self.value.zeroize();
+        wipe_scalar(&mut self.blinding);
+    }
+}
+
+impl Zeroize for TrueInput {
+    fn zeroize(&mut self) {
+        wipe_scalar(&mut self.secret_key);
+        self.revealed_commitment.zeroize();
+    }
+}
+
+impl Zeroize for MlsagMaterial {
+    fn zeroize(&mut self) {
+        self.true_input.zeroize();
+        self.secrets.zeroize();
+    }
+}
+
+impl Zeroize for MlsagSecrets {
+    fn zeroize(&mut self) {
+        wipe_scalar(&mut self.alpha.0);
+        wipe_scalar(&mut self.alpha.1);
+        for (r0, r1) in self.r.iter_mut() {
+            wipe_scalar(r0);
+            wipe_scalar(r1);
+        }
+        self.pi_base.zeroize();
+    }
+}
+
+impl Zeroize for StealthSecretKeys {
+    fn zeroize(&mut self) {
+        wipe_scalar(&mut self.view);
+        wipe_scalar(&mut self.spend);
+    }
+}
+
+impl Zeroize for TransactionKey {
+    fn zeroize(&mut self) {
+        wipe_scalar(&mut self.secret);
+    }
+}
+
+impl Zeroize for SharedSecret {
+    fn zeroize(&mut self) {
+        wipe(&mut self.0, G1Affine::identity());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn test_zeroize() {
+        let mut input = TrueInput {
+            secret_key: Scalar::random(OsRng),
+            revealed_commitment: RevealedCommitment::from_value(7, OsRng),
+        };
+        input.zeroize();
+        assert_eq!(input.secret_key, Scalar::zero());
+        assert_eq!(input.revealed_commitment.value, 0);
+        assert_eq!(input.revealed_commitment.blinding, Scalar::zero());
+
+        let mut secrets = MlsagSecrets {
+            pi_base: 3,
+            alpha: (Scalar::random(OsRng), Scalar::random(OsRng)),
+            r: vec![(Scalar::random(OsRng), Scalar::random(OsRng))],
+        };
+        secrets.zeroize();
+        assert_eq!(secrets.pi_base, 0);
+        assert_eq!(secrets.alpha, (Scalar::zero(), Scalar::zero()));
+        assert_eq!(secrets.r, vec![(Scalar::zero(), Scalar::zero())]);
+
+        let mut keys = StealthSecretKeys::random(OsRng);
+        keys.zeroize();
+        assert_eq!(keys.view, Scalar::zero());
+        assert_eq!(keys.spend, Scalar::zero());
+    }
+}